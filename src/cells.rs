@@ -0,0 +1,309 @@
+use crate::poly::first_descent;
+use crate::vec3::V;
+
+pub type Cell = [usize; 3];
+
+/// Uniform grid over the box `lo`..`lo + n * size`.
+/// Along the periodic axes the grid wraps around, along the others it has one more cell
+/// on each side and the outer cells extend to infinity such that every position belongs to a cell.
+#[derive(Debug, Clone, Default)]
+pub struct Cells {
+    lo: V,
+    size: V,
+    n: [usize; 3],
+    periodic: [bool; 3],
+    // the images above and below slide along x
+    sheared: bool,
+    members: Vec<Vec<usize>>,
+    // walls that reach each cell
+    walls: Vec<Vec<usize>>,
+}
+
+impl Cells {
+    /// Cells of size at least `range`, with at most `max_cells` of them
+    pub fn new(
+        lo: V,
+        hi: V,
+        periodic: [bool; 3],
+        sheared: bool,
+        mut range: f64,
+        max_cells: usize,
+    ) -> Cells {
+        let count = |range: f64| {
+            let mut n = [1; 3];
+            for d in 0..3 {
+                let l = hi[d] - lo[d];
+                if range > 0.0 && l > range {
+                    n[d] = (l / range).floor() as usize;
+                }
+            }
+            n
+        };
+        let mut n = count(range);
+        while n[0] * n[1] * n[2] > max_cells.max(1) {
+            range *= 1.25;
+            n = count(range);
+        }
+        let mut lo = lo;
+        let mut size = V::new(0.0, 0.0, 0.0);
+        for d in 0..3 {
+            size[d] = (hi[d] - lo[d]) / n[d] as f64;
+            // what reaches the box from afar only gets into the outer cells
+            if !periodic[d] && size[d] > 0.0 {
+                lo[d] -= size[d];
+                n[d] += 2;
+            }
+        }
+        Cells {
+            lo,
+            size,
+            n,
+            periodic,
+            sheared,
+            members: vec![Vec::new(); n[0] * n[1] * n[2]],
+            walls: vec![Vec::new(); n[0] * n[1] * n[2]],
+        }
+    }
+
+    pub fn count(&self, d: usize) -> usize {
+        self.n[d]
+    }
+
+    fn flat(&self, c: Cell) -> usize {
+        (c[0] * self.n[1] + c[1]) * self.n[2] + c[2]
+    }
+
+    pub fn cell(&self, x: V) -> Cell {
+        let mut c = [0; 3];
+        for d in 0..3 {
+            let k = ((x[d] - self.lo[d]) / self.size[d]).floor();
+            c[d] = if k > 0.0 {
+                (k as usize).min(self.n[d] - 1)
+            } else {
+                0
+            };
+        }
+        c
+    }
+
+    pub fn insert(&mut self, i: usize, c: Cell) {
+        let f = self.flat(c);
+        self.members[f].push(i);
+    }
+
+    pub fn remove(&mut self, i: usize, c: Cell) {
+        let f = self.flat(c);
+        let m = &mut self.members[f];
+        if let Some(p) = m.iter().position(|&k| k == i) {
+            m.swap_remove(p);
+        }
+    }
+
+    /// Register the wall `j` in the cells that overlap the box `lo`..`hi`
+    pub fn insert_wall(&mut self, j: usize, lo: V, hi: V) {
+        let (a, b) = (self.cell(lo), self.cell(hi));
+        for x in a[0]..=b[0] {
+            for y in a[1]..=b[1] {
+                for z in a[2]..=b[2] {
+                    let f = self.flat([x, y, z]);
+                    self.walls[f].push(j);
+                }
+            }
+        }
+    }
+
+    /// Walls that reach the cell `c`
+    pub fn walls(&self, c: Cell) -> &[usize] {
+        &self.walls[self.flat(c)]
+    }
+
+    // indices `k + o` along the axis `d`, wrapped around if periodic
+    fn span(&self, d: usize, k: usize, offsets: &[isize]) -> Vec<usize> {
+        let n = self.n[d] as isize;
+        let mut out = Vec::new();
+        for &o in offsets {
+            let mut x = k as isize + o;
+            if self.periodic[d] {
+                x = x.rem_euclid(n);
+            } else if x < 0 || x >= n {
+                continue;
+            }
+            if !out.contains(&(x as usize)) {
+                out.push(x as usize);
+            }
+        }
+        out
+    }
+
+    fn collect(&self, spans: [Vec<usize>; 3]) -> Vec<usize> {
+        let mut out = Vec::new();
+        for &x in &spans[0] {
+            for &y in &spans[1] {
+                for &z in &spans[2] {
+                    out.extend_from_slice(&self.members[self.flat([x, y, z])]);
+                }
+            }
+        }
+        out
+    }
+
+    // indices along x, all of them if the rows at `y + o` are seen through the sheared boundaries
+    // because the neighbors there can be anywhere along x
+    fn span_x(&self, c: Cell, x_offsets: &[isize], y_offsets: &[isize]) -> Vec<usize> {
+        let n = self.n[1] as isize;
+        let across = y_offsets.iter().any(|&o| {
+            let y = c[1] as isize + o;
+            y < 0 || y >= n
+        });
+        if self.sheared && across {
+            (0..self.n[0]).collect()
+        } else {
+            self.span(0, c[0], x_offsets)
+        }
+    }
+
+    /// Balls in the cell `c` and in its neighbors
+    pub fn around(&self, c: Cell) -> Vec<usize> {
+        let o = [-1, 0, 1];
+        self.collect([self.span_x(c, &o, &o), self.span(1, c[1], &o), self.span(2, c[2], &o)])
+    }
+
+    /// Balls that became neighbors after moving into `c` along `axis` in direction `dir`
+    pub fn layer(&self, c: Cell, axis: usize, dir: isize) -> Vec<usize> {
+        if self.periodic[axis] && self.n[axis] <= 3 {
+            // the whole axis was already in the neighborhood
+            return Vec::new();
+        }
+        let offsets = |d: usize| if d == axis { vec![dir] } else { vec![-1, 0, 1] };
+        self.collect([
+            self.span_x(c, &offsets(0), &offsets(1)),
+            self.span(1, c[1], &offsets(1)),
+            self.span(2, c[2], &offsets(2)),
+        ])
+    }
+
+    /// Time before a ball at `x` with velocity `v` and acceleration `a` leaves the cell `c`,
+    /// with the axis and direction
+    pub fn exit(&self, c: Cell, x: V, v: V, a: V) -> (f64, usize, isize) {
+        let mut best = (f64::INFINITY, 0, 0);
+        for d in 0..3 {
+            if self.periodic[d] || c[d] + 1 < self.n[d] {
+                let b = self.lo[d] + (c[d] + 1) as f64 * self.size[d];
+                let t = first_descent(&[b - x[d], -v[d], -0.5 * a[d]]);
+                if t < best.0 {
+                    best = (t, d, 1);
+                }
+            }
+            if self.periodic[d] || c[d] > 0 {
+                let b = self.lo[d] + c[d] as f64 * self.size[d];
+                let t = first_descent(&[x[d] - b, v[d], 0.5 * a[d]]);
+                if t < best.0 {
+                    best = (t, d, -1);
+                }
+            }
+        }
+        best
+    }
+
+    /// Neighbor cell of `c`, and whether the step wrapped around the periodic grid
+    pub fn step(&self, c: Cell, axis: usize, dir: isize) -> (Cell, bool) {
+        let mut c = c;
+        let k = c[axis] as isize + dir;
+        let n = self.n[axis] as isize;
+        c[axis] = k.rem_euclid(n) as usize;
+        (c, k < 0 || k >= n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::periodic::Periodic;
+    use crate::rng::Rng;
+
+    #[test]
+    fn neighbors_are_around() {
+        let l = V::new(4.0, 5.0, 6.0);
+        let p = Periodic {
+            l,
+            p: [true, true, false],
+            shear: 0.0,
+        };
+        let mut cells = Cells::new(-0.5 * l, 0.5 * l, p.p, false, 0.7, 1000);
+        let mut rng = Rng::new(1);
+        let x: Vec<V> = (0..300)
+            .map(|_| {
+                V::new(
+                    (rng.uniform() - 0.5) * l.0,
+                    (rng.uniform() - 0.5) * l.1,
+                    (1.2 * rng.uniform() - 0.6) * l.2,
+                )
+            })
+            .collect();
+        for (i, &x) in x.iter().enumerate() {
+            cells.insert(i, cells.cell(x));
+        }
+        for i in 0..x.len() {
+            let around = cells.around(cells.cell(x[i]));
+            for k in 0..x.len() {
+                if p.image(x[k] - x[i]).norm() < 0.7 {
+                    assert!(around.contains(&k), "{} and {} are not neighbors", i, k);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exit_and_step() {
+        let cells = Cells::new(
+            V::new(0.0, 0.0, 0.0),
+            V::new(3.0, 3.0, 3.0),
+            [true, false, false],
+            false,
+            1.0,
+            100,
+        );
+        // with one more cell on each side along y and z
+        let c = cells.cell(V::new(2.5, 0.5, 0.5));
+        assert_eq!(c, [2, 1, 1]);
+        let (t, axis, dir) = cells.exit(
+            c,
+            V::new(2.5, 0.5, 0.5),
+            V::new(1.0, 0.1, 0.0),
+            V::new(0.0, 0.0, 0.0),
+        );
+        assert!((t - 0.5).abs() < 1e-12);
+        assert_eq!((axis, dir), (0, 1));
+        assert_eq!(cells.step(c, 0, 1), ([0, 1, 1], true));
+        assert_eq!(cells.step(c, 1, 1), ([2, 2, 1], false));
+        // the outer cells of the other axes extend to infinity
+        let (t, _, _) = cells.exit(
+            [0, 4, 1],
+            V::new(0.5, 3.5, 0.5),
+            V::new(0.0, 1.0, 0.0),
+            V::new(0.0, 0.0, 0.0),
+        );
+        assert_eq!(t, f64::INFINITY);
+    }
+
+    #[test]
+    fn walls() {
+        let mut cells = Cells::new(
+            V::new(0.0, 0.0, 0.0),
+            V::new(4.0, 4.0, 4.0),
+            [false; 3],
+            false,
+            1.0,
+            100,
+        );
+        cells.insert_wall(7, V::new(0.5, 1.5, -1.0), V::new(1.5, 2.5, -0.5));
+        assert_eq!(cells.walls([2, 3, 0]), &[7]);
+        assert_eq!(cells.walls([1, 2, 0]), &[7]);
+        assert!(cells.walls([1, 2, 1]).is_empty());
+        assert!(cells.walls([3, 2, 0]).is_empty());
+        // far away, only in an outer cell
+        cells.insert_wall(8, V::new(10.0, 10.0, 10.0), V::new(11.0, 11.0, 11.0));
+        assert_eq!(cells.walls([5, 5, 5]), &[8]);
+        assert!(cells.walls([4, 4, 4]).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use crate::vec3::V;
+use std::f64::consts::PI;
+
+/// Collisions and free flights of the balls since `start`. The flights are the paths between
+/// two collisions of a ball, as straight lines.
+#[derive(Debug, Clone, Default)]
+pub struct Collisions {
+    // per ball, with the other balls and with the walls and containers
+    pub ball_ball: Vec<u64>,
+    pub ball_wall: Vec<u64>,
+    pub flights: u64,
+    pub flight_time: f64,
+    pub flight_distance: f64,
+    pub start: f64,
+    // time and unwrapped position of the last collision of each ball
+    last: Vec<Option<(f64, V)>>,
+}
+
+impl Collisions {
+    pub fn add_ball(&mut self) {
+        self.ball_ball.push(0);
+        self.ball_wall.push(0);
+        self.last.push(None);
+    }
+
+    /// Forget everything, counting from `t`
+    pub fn reset(&mut self, t: f64) {
+        let n = self.last.len();
+        *self = Collisions {
+            ball_ball: vec![0; n],
+            ball_wall: vec![0; n],
+            start: t,
+            last: vec![None; n],
+            ..Collisions::default()
+        };
+    }
+
+    /// Collision of the ball `i` at time `t` and unwrapped position `x`
+    pub fn collide(&mut self, i: usize, wall: bool, t: f64, x: V) {
+        if wall {
+            self.ball_wall[i] += 1;
+        } else {
+            self.ball_ball[i] += 1;
+        }
+        if let Some((t0, x0)) = self.last[i] {
+            self.flights += 1;
+            self.flight_time += t - t0;
+            self.flight_distance += (x - x0).norm();
+        }
+        self.last[i] = Some((t, x));
+    }
+}
+
+/// Collision frequency per ball and mean free path of hard spheres of diameter `sigma` and mass `m`
+/// at the number density `n` and temperature `t`, from Enskog theory with the Carnahan-Starling
+/// contact value of the pair correlation
+pub fn enskog(n: f64, sigma: f64, m: f64, t: f64) -> (f64, f64) {
+    let eta = PI / 6.0 * n * sigma.powi(3);
+    let g = (1.0 - eta / 2.0) / (1.0 - eta).powi(3);
+    let frequency = 4.0 * n * sigma * sigma * g * (PI * t / m).sqrt();
+    let mean_speed = (8.0 * t / (PI * m)).sqrt();
+    (frequency, mean_speed / frequency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dilute_and_dense() {
+        // kinetic theory of a dilute gas
+        let (n, sigma, m, t) = (1e-4, 1.0, 2.0, 3.0);
+        let (frequency, path) = enskog(n, sigma, m, t);
+        assert!((frequency / (4.0 * n * (PI * t / m).sqrt()) - 1.0).abs() < 1e-3);
+        assert!((path * 2f64.sqrt() * PI * n - 1.0).abs() < 1e-3);
+        // more often than in a dilute gas by the contact value, at the packing fraction 0.4
+        let n = 0.4 * 6.0 / PI;
+        let (dense, _) = enskog(n, 1.0, 1.0, 1.0);
+        assert!((dense / (4.0 * n * PI.sqrt()) - 0.8 / 0.6f64.powi(3)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn flights() {
+        let mut c = Collisions::default();
+        c.add_ball();
+        c.add_ball();
+        c.collide(0, false, 1.0, V::new(0.0, 0.0, 0.0));
+        c.collide(1, false, 1.0, V::new(1.0, 0.0, 0.0));
+        assert_eq!(c.flights, 0);
+        c.collide(0, true, 3.0, V::new(3.0, 4.0, 0.0));
+        c.collide(0, false, 4.0, V::new(3.0, 4.0, 1.0));
+        assert_eq!(
+            (c.ball_ball.clone(), c.ball_wall.clone()),
+            (vec![2, 1], vec![1, 0])
+        );
+        assert_eq!((c.flights, c.flight_time, c.flight_distance), (2, 3.0, 6.0));
+        c.reset(5.0);
+        assert_eq!(
+            (c.ball_ball.clone(), c.flights, c.start),
+            (vec![0, 0], 0, 5.0)
+        );
+        // the first collision after the reset starts a flight
+        c.collide(0, false, 6.0, V::default());
+        assert_eq!(c.flights, 0);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::dynamics::{Ball, Wall};
+use crate::vec3::{cross, dot, V};
+
+/// Conserved quantities of the balls and of the walls free to move
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Totals {
+    // translational and rotational
+    pub kinetic: f64,
+    // steps of the potential and accelerations
+    pub potential: f64,
+    pub momentum: V,
+    // around the origin, the walls count as points at their centroid
+    pub angular_momentum: V,
+    // of the balls only
+    pub center_of_mass: V,
+    pub mass: f64,
+    // sum of `m |x|^2` around the origin
+    pub second_moment: f64,
+}
+
+impl Totals {
+    pub fn energy(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+}
+
+/// Totals of the balls in the global acceleration `g`, with the energy `potential` of the steps.
+/// The walls with an infinite mass or a prescribed motion are left out.
+pub fn totals(balls: &[Ball], walls: &[Wall], g: V, potential: f64) -> Totals {
+    let mut s = Totals {
+        potential,
+        ..Totals::default()
+    };
+    for a in balls {
+        s.kinetic += 0.5 * a.m * dot(a.v, a.v) + 0.5 * a.i * dot(a.w, a.w);
+        s.potential -= a.m * dot(g + a.a, a.x);
+        s.momentum += a.m * a.v;
+        s.angular_momentum += a.m * cross(a.x, a.v) + a.i * a.w;
+        s.center_of_mass += a.m * a.x;
+        s.mass += a.m;
+        s.second_moment += a.m * dot(a.x, a.x);
+    }
+    if s.mass > 0.0 {
+        s.center_of_mass /= s.mass;
+    }
+    for w in walls {
+        if w.m.is_finite() && w.motion.is_none() {
+            let c = w.corners();
+            let x = w.x + c.iter().fold(V::default(), |s, &c| s + c) / c.len() as f64;
+            s.kinetic += 0.5 * w.m * dot(w.v, w.v);
+            s.momentum += w.m * w.v;
+            s.angular_momentum += w.m * cross(x, w.v);
+            s.second_moment += w.m * dot(x, x);
+            s.mass += w.m;
+        }
+    }
+    s
+}
+
+/// Drift of the energy, momentum and angular momentum since the reference, relative to
+/// their typical magnitudes
+#[derive(Debug, Clone, Copy)]
+pub struct Drift {
+    pub energy: f64,
+    pub momentum: f64,
+    pub angular_momentum: f64,
+}
+
+/// Records the drift of the conserved quantities and tells when it exceeds the tolerance
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    pub tolerance: f64,
+    // quantities checked against the tolerance, all are recorded
+    pub energy: bool,
+    pub momentum: bool,
+    pub angular_momentum: bool,
+    pub reference: Totals,
+    pub records: Vec<(f64, Drift)>,
+}
+
+impl Monitor {
+    pub fn new(reference: Totals, tolerance: f64) -> Monitor {
+        Monitor {
+            tolerance,
+            energy: true,
+            momentum: false,
+            angular_momentum: false,
+            reference,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn drift(&self, s: &Totals) -> Drift {
+        let r = &self.reference;
+        // bounds of the magnitudes of the sums by Cauchy-Schwarz
+        let e = r.kinetic.abs() + r.potential.abs();
+        let p = (2.0 * r.mass * r.kinetic).sqrt();
+        let l = (2.0 * r.second_moment * r.kinetic).sqrt();
+        let relative = |d: f64, scale: f64| if scale > 0.0 { d / scale } else { d };
+        Drift {
+            energy: relative((s.energy() - r.energy()).abs(), e),
+            momentum: relative((s.momentum - r.momentum).norm(), p),
+            angular_momentum: relative((s.angular_momentum - r.angular_momentum).norm(), l),
+        }
+    }
+
+    /// Record the totals at time `t`, returns a description of the excess if there is one
+    pub fn record(&mut self, t: f64, s: &Totals) -> Option<String> {
+        let d = self.drift(s);
+        self.records.push((t, d));
+        let checks = [
+            (self.energy, d.energy, "energy"),
+            (self.momentum, d.momentum, "momentum"),
+            (self.angular_momentum, d.angular_momentum, "angular momentum"),
+        ];
+        checks
+            .iter()
+            .find(|c| c.0 && (c.1 > self.tolerance || c.1.is_nan()))
+            .map(|c| format!("relative drift of the {} of {:e} at t = {}", c.2, c.1, t))
+    }
+}
+
+pub type Tensor = [[f64; 3]; 3];
+
+/// Add the tensor product `x y` to `s`
+pub fn add_outer(s: &mut Tensor, x: V, y: V) {
+    for (p, row) in s.iter_mut().enumerate() {
+        for (q, c) in row.iter_mut().enumerate() {
+            *c += x[p] * y[q];
+        }
+    }
+}
+
+/// Add the integral of `m u u` over `dt` to `s`, for the velocity `u + b t`
+pub fn add_kinetic(s: &mut Tensor, m: f64, u: V, b: V, dt: f64) {
+    for (p, row) in s.iter_mut().enumerate() {
+        for (q, c) in row.iter_mut().enumerate() {
+            *c += m
+                * (u[p] * u[q] * dt
+                    + (u[p] * b[q] + b[p] * u[q]) * dt * dt / 2.0
+                    + b[p] * b[q] * dt * dt * dt / 3.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamics::Shape;
+
+    fn ball(x: V, v: V) -> Ball {
+        Ball {
+            x,
+            v,
+            a: V::default(),
+            w: V::new(0.0, 0.0, 2.0),
+            m: 2.0,
+            r: 0.5,
+            i: 0.1,
+            e: None,
+            s: 0,
+        }
+    }
+
+    fn wall(m: f64) -> Wall {
+        Wall {
+            x: V::new(0.0, 0.0, 1.0),
+            v: V::new(0.0, 0.0, 1.0),
+            j: V::new(2.0, 0.0, 0.0),
+            k: V::new(0.0, 2.0, 0.0),
+            m,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
+        }
+    }
+
+    fn close(a: V, b: V) -> bool {
+        (a - b).norm() < 1e-12
+    }
+
+    #[test]
+    fn totals_of_balls_and_walls() {
+        let balls = [
+            ball(V::new(1.0, 0.0, 0.0), V::new(0.0, 1.0, 0.0)),
+            ball(V::new(-1.0, 0.0, 0.0), V::new(0.0, 0.0, 0.0)),
+        ];
+        let g = V::new(0.0, 0.0, -1.0);
+        let s = totals(&balls, &[wall(f64::INFINITY)], g, -0.5);
+        assert!((s.kinetic - 1.4).abs() < 1e-12);
+        assert_eq!(s.potential, -0.5);
+        assert_eq!(s.momentum, V::new(0.0, 2.0, 0.0));
+        assert!(close(s.angular_momentum, V::new(0.0, 0.0, 2.4)));
+        assert_eq!(s.center_of_mass, V::default());
+        assert_eq!((s.mass, s.second_moment), (4.0, 4.0));
+
+        // a free wall counts as a point at its centroid
+        let s = totals(&balls, &[wall(3.0)], g, 0.0);
+        assert!((s.kinetic - 2.9).abs() < 1e-12);
+        assert_eq!(s.momentum, V::new(0.0, 2.0, 3.0));
+        assert!(close(s.angular_momentum, V::new(3.0, -3.0, 2.4)));
+        assert_eq!((s.mass, s.second_moment), (7.0, 4.0 + 3.0 * 3.0));
+        assert_eq!(s.center_of_mass, V::default());
+    }
+
+    #[test]
+    fn monitor() {
+        let balls = [ball(V::new(1.0, 0.0, 0.0), V::new(0.0, 1.0, 0.0))];
+        let s = totals(&balls, &[], V::default(), 0.0);
+        let mut m = Monitor::new(s, 1e-6);
+        assert_eq!(m.record(0.0, &s), None);
+
+        let faster = [ball(V::new(1.0, 0.0, 0.0), V::new(0.0, 1.001, 0.0))];
+        let d = totals(&faster, &[], V::default(), 0.0);
+        let e = m.record(1.0, &d).unwrap();
+        assert!(e.starts_with("relative drift of the energy"), "{}", e);
+        assert_eq!(m.records.len(), 2);
+        let drift = m.records[1].1;
+        assert!((drift.energy - (d.kinetic - s.kinetic) / s.kinetic).abs() < 1e-12);
+        assert!(drift.momentum > 0.0 && drift.angular_momentum > 0.0);
+
+        // only the checked quantities count
+        m.energy = false;
+        assert_eq!(m.record(2.0, &d), None);
+        m.momentum = true;
+        assert!(m.record(3.0, &d).unwrap().contains("momentum"));
+        let nan = Totals {
+            kinetic: f64::NAN,
+            ..s
+        };
+        m.energy = true;
+        assert!(m.record(4.0, &nan).is_some());
+    }
+
+    #[test]
+    fn tensors() {
+        let mut s = [[0.0; 3]; 3];
+        add_outer(&mut s, V::new(1.0, 2.0, 0.0), V::new(0.0, 1.0, 3.0));
+        assert_eq!(s, [[0.0, 1.0, 3.0], [0.0, 2.0, 6.0], [0.0, 0.0, 0.0]]);
+
+        // against the midpoint rule
+        let (m, u, b, dt) = (2.0, V::new(1.0, -1.0, 0.5), V::new(0.0, 0.0, -2.0), 1.5);
+        let mut s = [[0.0; 3]; 3];
+        add_kinetic(&mut s, m, u, b, dt);
+        let mut r = [[0.0; 3]; 3];
+        let n = 1000;
+        for k in 0..n {
+            let v = u + (k as f64 + 0.5) * dt / n as f64 * b;
+            add_outer(&mut r, m * dt / n as f64 * v, v);
+        }
+        for p in 0..3 {
+            for q in 0..3 {
+                assert!((s[p][q] - r[p][q]).abs() < 1e-5, "{} {}", p, q);
+            }
+        }
+    }
+}
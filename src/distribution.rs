@@ -0,0 +1,174 @@
+use std::f64::consts::PI;
+
+/// Probability density over `bins` equal bins between `lo` and `hi`,
+/// the values outside are left out of the bins but not of the normalization
+pub fn histogram(x: &[f64], bins: usize, lo: f64, hi: f64) -> Vec<f64> {
+    let w = (hi - lo) / bins as f64;
+    let mut h = vec![0.0; bins];
+    for &x in x {
+        let b = ((x - lo) / w).floor();
+        if b >= 0.0 && (b as usize) < bins {
+            h[b as usize] += 1.0;
+        } else if x == hi {
+            h[bins - 1] += 1.0;
+        }
+    }
+    h.iter().map(|c| c / (x.len() as f64 * w)).collect()
+}
+
+/// Error function, to about 1e-7
+pub fn erf(x: f64) -> f64 {
+    // Abramowitz and Stegun 7.1.26
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let p = t
+        * (0.254_829_592
+            + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let y = 1.0 - p * (-x * x).exp();
+    if x < 0.0 {
+        -y
+    } else {
+        y
+    }
+}
+
+/// Maxwell-Boltzmann distribution of the velocities of balls of various masses at a temperature
+#[derive(Debug, Clone)]
+pub struct Maxwell {
+    // distinct masses with the fraction of the balls that have them
+    masses: Vec<(f64, f64)>,
+    t: f64,
+}
+
+impl Maxwell {
+    pub fn new(masses: &[f64], t: f64) -> Maxwell {
+        let mut m = masses.to_vec();
+        m.sort_by(f64::total_cmp);
+        let mut out: Vec<(f64, f64)> = Vec::new();
+        for &m in &m {
+            match out.last_mut() {
+                Some(last) if last.0 == m => last.1 += 1.0,
+                _ => out.push((m, 1.0)),
+            }
+        }
+        for f in out.iter_mut() {
+            f.1 /= masses.len() as f64;
+        }
+        Maxwell { masses: out, t }
+    }
+
+    // sum of `f(x / sigma)` weighted by the fractions, with `sigma` the velocity scale of each mass
+    fn mix(&self, x: f64, f: impl Fn(f64) -> f64) -> f64 {
+        self.masses
+            .iter()
+            .map(|&(m, w)| w * f(x / (self.t / m).sqrt()))
+            .sum()
+    }
+
+    /// Cumulative distribution of one component of the velocity
+    pub fn component_cdf(&self, x: f64) -> f64 {
+        self.mix(x, |s| 0.5 * (1.0 + erf(s / 2f64.sqrt())))
+    }
+
+    /// Cumulative distribution of the speed
+    pub fn speed_cdf(&self, v: f64) -> f64 {
+        if v <= 0.0 {
+            return 0.0;
+        }
+        self.mix(v, |s| erf(s / 2f64.sqrt()) - (2.0 / PI).sqrt() * s * (-0.5 * s * s).exp())
+    }
+}
+
+/// Kolmogorov-Smirnov statistic of the sample `x` against the cumulative distribution `cdf`
+pub fn ks_statistic(x: &[f64], cdf: impl Fn(f64) -> f64) -> f64 {
+    let mut x = x.to_vec();
+    x.sort_by(f64::total_cmp);
+    let n = x.len() as f64;
+    x.iter().enumerate().fold(0.0, |d, (i, &x)| {
+        let f = cdf(x);
+        d.max(f - i as f64 / n).max((i + 1) as f64 / n - f)
+    })
+}
+
+/// Kullback-Leibler divergence of the histogram of `x` over `bins` bins between `lo` and `hi`
+/// from the cumulative distribution `cdf`
+pub fn kl_divergence(x: &[f64], bins: usize, lo: f64, hi: f64, cdf: impl Fn(f64) -> f64) -> f64 {
+    let w = (hi - lo) / bins as f64;
+    let h = histogram(x, bins, lo, hi);
+    let mut d = 0.0;
+    for (b, h) in h.iter().enumerate() {
+        let p = h * w;
+        let q = cdf(lo + (b + 1) as f64 * w) - cdf(lo + b as f64 * w);
+        if p > 0.0 {
+            d += p * (p / q).ln();
+        }
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn error_function() {
+        let values = [
+            (0.0, 0.0),
+            (0.5, 0.520_499_877_8),
+            (1.0, 0.842_700_792_9),
+            (2.0, 0.995_322_265_0),
+            (3.0, 0.999_977_909_5),
+        ];
+        for &(x, y) in &values {
+            assert!((erf(x) - y).abs() < 2e-7, "erf({}) = {}", x, erf(x));
+            assert!((erf(-x) + y).abs() < 2e-7);
+        }
+    }
+
+    #[test]
+    fn histograms() {
+        let x = [0.0, 0.1, 0.6, 1.0, 1.5, -0.2];
+        // the last bin takes the upper edge, the values outside still count
+        let h = histogram(&x, 2, 0.0, 1.0);
+        assert_eq!(h, vec![2.0 / 3.0, 2.0 / 3.0]);
+        let h = histogram(&x, 4, -1.0, 3.0);
+        assert_eq!(h.iter().sum::<f64>(), 1.0);
+    }
+
+    #[test]
+    fn distances() {
+        let uniform = |x: f64| x.clamp(0.0, 1.0);
+        let x: Vec<f64> = (0..10).map(|i| (i as f64 + 0.5) / 10.0).collect();
+        assert!((ks_statistic(&x, uniform) - 0.05).abs() < 1e-12);
+        assert!((ks_statistic(&[0.0; 4], uniform) - 1.0).abs() < 1e-12);
+        assert!(kl_divergence(&x, 5, 0.0, 1.0, uniform).abs() < 1e-12);
+        // everything in one of two equally likely bins
+        let d = kl_divergence(&[0.1, 0.2, 0.3], 2, 0.0, 1.0, uniform);
+        assert!((d - 2f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn maxwell() {
+        let masses = [1.0, 1.0, 4.0];
+        let mb = Maxwell::new(&masses, 2.0);
+        assert!((mb.component_cdf(0.0) - 0.5).abs() < 1e-8);
+        assert_eq!(mb.speed_cdf(0.0), 0.0);
+        assert!((mb.speed_cdf(100.0) - 1.0).abs() < 1e-7);
+
+        // samples at the temperature are close, and far at another one
+        let mut rng = Rng::new(1);
+        let mut x = Vec::new();
+        let mut speed = Vec::new();
+        for k in 0..3000 {
+            let s = (2.0 / masses[k % 3]).sqrt();
+            let v = [s * rng.normal(), s * rng.normal(), s * rng.normal()];
+            x.push(v[0]);
+            speed.push((v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt());
+        }
+        assert!(ks_statistic(&x, |v| mb.component_cdf(v)) < 0.03);
+        assert!(ks_statistic(&speed, |v| mb.speed_cdf(v)) < 0.03);
+        assert!(kl_divergence(&speed, 20, 0.0, 6.0, |v| mb.speed_cdf(v)) < 0.02);
+        let hot = Maxwell::new(&masses, 4.0);
+        assert!(ks_statistic(&speed, |v| hot.speed_cdf(v)) > 0.1);
+    }
+}
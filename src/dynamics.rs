@@ -1,13 +1,26 @@
 use core;
 
+use crate::motion::Motion;
+use crate::poly::{descents, first_descent};
+use crate::rng::Rng;
 use crate::vec3::{dot, cross, V};
 
 #[derive(Debug, Clone)]
 pub struct Ball {
     pub x: V,
     pub v: V,
+    // acceleration of this ball on top of the global one
+    pub a: V,
+    // angular velocity
+    pub w: V,
     pub m: f64,
     pub r: f64,
+    // moment of inertia, the ball does not rotate if zero
+    pub i: f64,
+    // coefficient of restitution, the global one if `None`
+    pub e: Option<f64>,
+    // species, see `Interaction`
+    pub s: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -17,13 +30,188 @@ pub struct Wall {
     pub j: V,
     pub k: V,
     pub m: f64,
+    // coefficient of restitution, the one of the ball if `None`
+    pub e: Option<f64>,
+    // thermal wall, the balls are re-emitted with a Maxwellian distribution at this temperature
+    pub temperature: Option<f64>,
+    // prescribed trajectory, the wall is then not affected by the collisions
+    pub motion: Option<Motion>,
+    pub shape: Shape,
 }
 
-fn ball_ball_collision_time(a: &Ball, b: &Ball) -> f64 {
-    // go in frame of `a`
+/// Shape of a wall in the plane spanned by `j` and `k`
+#[derive(Debug, Clone)]
+pub enum Shape {
+    // corners `x`, `x + j`, `x + j + k` and `x + k`
+    Parallelogram,
+    // corners `x`, `x + j` and `x + k`
+    Triangle,
+    // convex polygon with the corners `x + u j + v k` given as `(u, v)`, in order
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl Wall {
+    /// Corners as `(u, v)` such that they are at `x + u j + v k`, in order
+    pub fn outline(&self) -> Vec<(f64, f64)> {
+        match &self.shape {
+            Shape::Parallelogram => vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            Shape::Triangle => vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            Shape::Polygon(c) => c.clone(),
+        }
+    }
+
+    /// Corners relative to `x`
+    pub fn corners(&self) -> Vec<V> {
+        self.outline()
+            .iter()
+            .map(|&(u, v)| u * self.j + v * self.k)
+            .collect()
+    }
+
+    /// Lowest and highest corners of the bounding box
+    pub fn bounds(&self) -> (V, V) {
+        let mut lo = V::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut hi = -lo;
+        for c in self.corners() {
+            for d in 0..3 {
+                lo[d] = lo[d].min(self.x[d] + c[d]);
+                hi[d] = hi[d].max(self.x[d] + c[d]);
+            }
+        }
+        (lo, hi)
+    }
+
+    pub fn area(&self) -> f64 {
+        let c = self.outline();
+        let uv: f64 = (0..c.len())
+            .map(|i| {
+                let (a, b) = (c[i], c[(i + 1) % c.len()]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum();
+        0.5 * uv.abs() * cross(self.j, self.k).norm()
+    }
+
+    // sides as the corner they start from, relative to `x`, and the vector to the next corner
+    fn sides(&self) -> Vec<(V, V)> {
+        let c = self.corners();
+        (0..c.len()).map(|i| (c[i], c[(i + 1) % c.len()] - c[i])).collect()
+    }
+
+    // whether `x + u j + v k` is on the wall
+    fn contains(&self, u: f64, v: f64) -> bool {
+        match &self.shape {
+            Shape::Parallelogram => (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v),
+            Shape::Triangle => u >= 0.0 && v >= 0.0 && u + v <= 1.0,
+            Shape::Polygon(c) => {
+                // on the same side of all the sides
+                let mut sign = 0.0;
+                for i in 0..c.len() {
+                    let (a, b) = (c[i], c[(i + 1) % c.len()]);
+                    let z = (b.0 - a.0) * (v - a.1) - (b.1 - a.1) * (u - a.0);
+                    if z * sign < 0.0 {
+                        return false;
+                    }
+                    if z != 0.0 {
+                        sign = z;
+                    }
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Position, sides `j` and `k` and shape of the flat convex polygon with the given corners, in order,
+/// they have to pass `polygon_problems`
+pub fn polygon(corners: &[V]) -> (V, V, V, Shape) {
+    let x = corners[0];
+    let j = corners[1] - x;
+    let k = corners[corners.len() - 1] - x;
+    if corners.len() == 3 {
+        return (x, j, k, Shape::Triangle);
+    }
+    let uv = corners.iter().map(|&c| plane_coordinates(j, k, c - x)).collect();
+    (x, j, k, Shape::Polygon(uv))
+}
+
+/// Curved boundary that keeps the balls inside
+#[derive(Debug, Clone)]
+pub enum Container {
+    Sphere {
+        center: V,
+        radius: f64,
+    },
+    // around the axis from `base` to `base + axis`, closed by flat caps at both ends
+    Cylinder {
+        base: V,
+        axis: V,
+        radius: f64,
+    },
+}
+
+/// Interaction between two species
+#[derive(Debug, Clone, Copy)]
+pub struct Interaction {
+    // contact distance, the sum of the radii if `None`
+    pub sigma: Option<f64>,
+    // coefficient of restitution, the one of the balls if `None`
+    pub e: Option<f64>,
+    pub interact: bool,
+}
+
+impl Default for Interaction {
+    fn default() -> Self {
+        Interaction {
+            sigma: None,
+            e: None,
+            interact: true,
+        }
+    }
+}
+
+/// Stepwise potential outside of the hard core, distances are in units of the contact distance:
+/// the energy is `steps[k].1` between `steps[k - 1].0` (or the contact) and `steps[k].0`
+#[derive(Debug, Clone, Default)]
+pub struct Potential {
+    pub steps: Vec<(f64, f64)>,
+}
+
+impl Potential {
+    /// Index of the step in which the distance `r` lies, `steps.len()` beyond the last one
+    pub fn level(&self, r: f64) -> usize {
+        self.steps
+            .iter()
+            .position(|&(l, _)| r < l)
+            .unwrap_or(self.steps.len())
+    }
+
+    pub fn energy(&self, level: usize) -> f64 {
+        self.steps.get(level).map_or(0.0, |s| s.1)
+    }
+
+    pub fn range(&self) -> f64 {
+        self.steps.last().map_or(1.0, |s| s.0)
+    }
+}
+
+/// Time before the distance between `a` and `b` reaches `r`, from above if `inward` and from below otherwise
+pub fn ball_ball_distance_time(a: &Ball, b: &Ball, r: f64, inward: bool) -> f64 {
+    // go in frame of `a`, the global acceleration cancels
     let x = b.x - a.x;
     let v = b.v - a.v;
-    let r = a.r + b.r;
+    let acc = b.a - a.a;
+    if acc != V::default() || !inward {
+        // |x + v t + acc t^2 / 2|^2 = r^2
+        let s = if inward { 1.0 } else { -1.0 };
+        return first_descent(&[
+            s * (dot(x, x) - r * r),
+            s * 2.0 * dot(x, v),
+            s * (dot(v, v) + dot(x, acc)),
+            s * dot(v, acc),
+            s * 0.25 * dot(acc, acc),
+        ]);
+    }
     let xv = dot(x, v);
     if xv >= 0.0 {
         core::f64::INFINITY
@@ -38,117 +226,629 @@ fn ball_ball_collision_time(a: &Ball, b: &Ball) -> f64 {
     }
 }
 
-fn ball_wall_collision_time(a: &Ball, w: &Wall) -> f64 {
+// times at which `|y + v t + acc t^2 / 2|` goes below `r`
+fn approach_times(y: V, v: V, acc: V, r: f64) -> Vec<f64> {
+    descents(&[
+        dot(y, y) - r * r,
+        2.0 * dot(y, v),
+        dot(v, v) + dot(y, acc),
+        dot(v, acc),
+        0.25 * dot(acc, acc),
+    ])
+}
+
+// coordinates of `y` along `j` and `k` once projected on their plane
+fn plane_coordinates(j: V, k: V, y: V) -> (f64, f64) {
+    let n = cross(j, k);
+    let nn = dot(n, n);
+    let ycn = cross(y, n);
+    (-dot(ycn, k) / nn, dot(ycn, j) / nn)
+}
+
+/// Point of the wall `w` closest to `x`
+pub fn wall_closest_point(w: &Wall, x: V) -> V {
+    let y = x - w.x;
+    let (u, v) = plane_coordinates(w.j, w.k, y);
+    if w.contains(u, v) {
+        return w.x + u * w.j + v * w.k;
+    }
+    let mut best = w.x;
+    for (p, d) in w.sides() {
+        let s = (dot(y - p, d) / dot(d, d)).clamp(0.0, 1.0);
+        let c = w.x + p + s * d;
+        if (c - x).norm() < (best - x).norm() {
+            best = c;
+        }
+    }
+    best
+}
+
+/// `g` is the global acceleration.
+/// The ball can hit the face of the wall, one of its edges or one of its corners.
+pub fn ball_wall_collision_time(a: &Ball, g: V, w: &Wall) -> f64 {
     // do in frame of `w`
-    let mut x = a.x - w.x;
+    let x = a.x - w.x;
     let v = a.v - w.v;
+    let acc = g + a.a;
+    let at = |t: f64| x + t * v + 0.5 * t * t * acc;
     let mut n = cross(w.j, w.k);
-    let njck = n.norm();
-    n /= njck;
+    n /= n.norm();
     let vn = dot(v, n);
     let xn = dot(x, n);
-    if xn * vn < 0.0 {
-        let t = -xn / vn - (a.r / vn).abs();
-        x += t * v;
-        let xcn = cross(x, n);
-        let a = -dot(xcn, w.k) / njck;
-        let b = dot(xcn, w.j) / njck;
-        if a < 0.0 || a > 1.0 || b < 0.0 || b > 1.0 {
-            core::f64::INFINITY
-        } else {
-            t
+    let an = dot(acc, n);
+    // side of the wall on which the ball is
+    let s = if xn != 0.0 { xn.signum() } else { -vn.signum() };
+    let inside = |t: f64| {
+        let (u, v) = plane_coordinates(w.j, w.k, at(t));
+        w.contains(u, v)
+    };
+    let face = descents(&[s * xn - a.r, s * vn, 0.5 * s * an]);
+    let mut best = face
+        .iter()
+        .copied()
+        .find(|&t| inside(t))
+        .unwrap_or(f64::INFINITY);
+
+    // the edges and corners are within `r` of the plane, they can only be hit
+    // if the ball gets near the wall while it is that close to the plane
+    let mut entries = face;
+    if s * xn <= a.r * (1.0 - 1e-9) {
+        entries.insert(0, 0.0);
+    }
+    let mut exits = descents(&[s * xn + a.r, s * vn, 0.5 * s * an]);
+    exits.extend(descents(&[a.r - s * xn, -s * vn, -0.5 * s * an]));
+    let corners = w.corners();
+    let near = entries.iter().filter(|&&t1| t1 < best).any(|&t1| {
+        let t2 = exits.iter().filter(|&&t| t > t1).fold(f64::INFINITY, |m, &t| m.min(t));
+        if t2.is_infinite() {
+            return true;
+        }
+        // bounding boxes of the path of the ball in the meantime and of the wall
+        (0..3).all(|d| {
+            let mut lo = at(t1)[d].min(at(t2)[d]);
+            let mut hi = at(t1)[d].max(at(t2)[d]);
+            if acc[d] != 0.0 {
+                let tv = -v[d] / acc[d];
+                if tv > t1 && tv < t2 {
+                    lo = lo.min(at(tv)[d]);
+                    hi = hi.max(at(tv)[d]);
+                }
+            }
+            let wlo = corners.iter().fold(f64::INFINITY, |m, c| m.min(c[d]));
+            let whi = corners.iter().fold(-f64::INFINITY, |m, c| m.max(c[d]));
+            hi >= wlo - a.r && lo <= whi + a.r
+        })
+    });
+    if !near {
+        return best;
+    }
+
+    // an edge or a corner is hit only if no other part of the wall is closer,
+    // it matters for balls that overlap the wall
+    let closest = |t: f64| {
+        let y = w.x + at(t);
+        (wall_closest_point(w, y) - y).norm() >= a.r * (1.0 - 1e-9)
+    };
+
+    // the edges are cylinders around the sides
+    for (p, d) in w.sides() {
+        let u = d / d.norm();
+        let perp = |y: V| y - dot(y, u) * u;
+        let along = |t: f64| (0.0..=dot(d, d)).contains(&dot(at(t) - p, d));
+        if let Some(t) = approach_times(perp(x - p), perp(v), perp(acc), a.r)
+            .into_iter()
+            .find(|&t| along(t) && closest(t))
+        {
+            best = best.min(t);
+        }
+    }
+
+    // and the corners are spheres
+    for &p in &corners {
+        if let Some(t) = approach_times(x - p, v, acc, a.r)
+            .into_iter()
+            .find(|&t| closest(t))
+        {
+            best = best.min(t);
         }
-    } else {
-        core::f64::INFINITY
     }
+    best
 }
 
-fn collision(n: V, mut va: V, ma: f64, mut vb: V, mb: f64) -> (V, V) {
-    if ma.is_infinite() {
-        return collision(n, vb, mb, va, ma);
+/// Time before `a` hits the wall `w` following its prescribed motion, `t` being the current time.
+/// The prediction may stop short of the collision, the second value tells whether it was reached.
+pub fn ball_driven_wall_collision_time(a: &Ball, g: V, w: &Wall, t: f64) -> (f64, bool) {
+    let motion = match &w.motion {
+        Some(m) => m,
+        None => return (ball_wall_collision_time(a, g, w), true),
+    };
+    // the velocity of the wall is constant until the next jump
+    let end = motion.next_jump(t) - t;
+    if motion.max_acceleration() == 0.0 {
+        let dt = ball_wall_collision_time(a, g, w);
+        return if dt <= end { (dt, true) } else { (end, false) };
     }
-    let fv = if mb.is_infinite() {
-        vb
-    } else {
-        (ma * va + mb * vb) / (ma + mb)
+
+    let acc = g + a.a;
+    // distance to contact, its rate of change and the relative speed, after `dt`
+    let gap = |dt: f64| {
+        let y = a.x + dt * a.v + 0.5 * dt * dt * acc - motion.offset(t + dt) + motion.offset(t);
+        let dv = a.v + dt * acc - motion.velocity(t + dt);
+        let d = y - wall_closest_point(w, y);
+        let l = d.norm();
+        let dl = if l > 0.0 { dot(d, dv) / l } else { 0.0 };
+        (l - a.r, dl, dv.norm())
     };
-    // go in rest frame
-    va -= fv;
-    vb -= fv;
-    va -= 2.0 * dot(n, va) * n / dot(n, n);
-    vb = -ma / mb * va;
-    // go back in original frame
-    va += fv;
-    vb += fv;
-    (va, vb)
-}
-
-fn ball_ball_collision(a: &Ball, b: &Ball) -> (V, V) {
+    // the distance to the wall is convex, it stays above its tangent minus the acceleration term
+    let m = acc.norm() + motion.max_acceleration();
+    let tol = 1e-10 * (a.r + (a.x - w.x).norm());
+
+    // conservative advancement, never beyond the first contact
+    let mut dt = 0.0;
+    for _ in 0..100 {
+        let (f, df, speed) = gap(dt);
+        if f <= tol && df < 0.0 {
+            return (dt, true);
+        }
+        let f = f.max(0.0);
+        let h = (df + (df * df + 2.0 * m * f).sqrt()) / m;
+        dt += h.max(tol / speed);
+        if dt >= end {
+            return (end, false);
+        }
+    }
+    (dt, false)
+}
+
+// times at which `|y + v t + acc t^2 / 2|` goes above `r`
+fn departure_times(y: V, v: V, acc: V, r: f64) -> Vec<f64> {
+    descents(&[
+        r * r - dot(y, y),
+        -2.0 * dot(y, v),
+        -dot(v, v) - dot(y, acc),
+        -dot(v, acc),
+        -0.25 * dot(acc, acc),
+    ])
+}
+
+/// Time before `a`, inside the container `c`, hits its boundary
+pub fn ball_container_collision_time(a: &Ball, g: V, c: &Container) -> f64 {
+    let acc = g + a.a;
+    match *c {
+        Container::Sphere { center, radius } => departure_times(a.x - center, a.v, acc, radius - a.r)
+            .first()
+            .copied()
+            .unwrap_or(f64::INFINITY),
+        Container::Cylinder { base, axis, radius } => {
+            let l = axis.norm();
+            let u = axis / l;
+            let perp = |y: V| y - dot(y, u) * u;
+            let y = a.x - base;
+            let side = departure_times(perp(y), perp(a.v), perp(acc), radius - a.r)
+                .first()
+                .copied()
+                .unwrap_or(f64::INFINITY);
+            // distance of the center to the caps
+            let (s, vs, as_) = (dot(y, u), dot(a.v, u), dot(acc, u));
+            let top = first_descent(&[l - a.r - s, -vs, -0.5 * as_]);
+            let bottom = first_descent(&[s - a.r, vs, 0.5 * as_]);
+            side.min(top).min(bottom)
+        }
+    }
+}
+
+/// Distance from `x` to the boundary of the container, negative outside
+pub fn container_clearance(x: V, c: &Container) -> f64 {
+    match *c {
+        Container::Sphere { center, radius } => radius - (x - center).norm(),
+        Container::Cylinder { base, axis, radius } => {
+            let l = axis.norm();
+            let u = axis / l;
+            let y = x - base;
+            let s = dot(y, u);
+            (radius - (y - s * u).norm()).min(s).min(l - s)
+        }
+    }
+}
+
+/// Unit normal pointing from the ball to the closest point of the boundary of the container
+pub fn ball_container_normal(a: &Ball, c: &Container) -> V {
+    match *c {
+        Container::Sphere { center, .. } => {
+            let n = a.x - center;
+            n / n.norm()
+        }
+        Container::Cylinder { base, axis, radius } => {
+            let l = axis.norm();
+            let u = axis / l;
+            let y = a.x - base;
+            let s = dot(y, u);
+            let p = y - s * u;
+            let rho = p.norm();
+            if l - s < (radius - rho).min(s) {
+                u
+            } else if s < radius - rho {
+                -u
+            } else {
+                p / rho
+            }
+        }
+    }
+}
+
+/// New velocity and angular velocity of `a` after hitting the boundary of the container
+pub fn ball_container_collision(a: &Ball, c: &Container, e: f64, beta: f64) -> (V, V) {
+    let n = ball_container_normal(a, c);
+    let g = a.v + cross(a.r * a.w, n);
+    let j = impulse(n, g, a.m, f64::INFINITY, rotational(a), e, beta);
+    (a.v + j / a.m, spin(a, n, j))
+}
+
+/// Impulse received by `a` when colliding with `b`.
+/// `n` is the unit normal pointing from `a` to `b` and `g` the relative velocity of the contact points.
+/// `k` is the sum of the squared distances to the contact point divided by the moments of inertia.
+/// `e` and `beta` are the normal and tangential coefficients of restitution.
+fn impulse(n: V, g: V, ma: f64, mb: f64, k: f64, e: f64, beta: f64) -> V {
+    let wn = 1.0 / ma + 1.0 / mb;
+    if wn == 0.0 {
+        return V::default();
+    }
+    let gn = dot(g, n);
+    let mut j = -(1.0 + e) * gn / wn * n;
+    if beta > -1.0 {
+        let gt = g - gn * n;
+        j -= (1.0 + beta) / (wn + k) * gt;
+    }
+    j
+}
+
+// inverse of the moment of inertia times the squared radius
+fn rotational(a: &Ball) -> f64 {
+    if a.i > 0.0 {
+        a.r * a.r / a.i
+    } else {
+        0.0
+    }
+}
+
+// angular velocity of `a` after receiving the impulse `j` at the contact point in direction `n`
+fn spin(a: &Ball, n: V, j: V) -> V {
+    if a.i > 0.0 {
+        a.w + a.r * cross(n, j) / a.i
+    } else {
+        a.w
+    }
+}
+
+pub fn ball_ball_normal(a: &Ball, b: &Ball) -> V {
     let n = b.x - a.x;
-    collision(n, a.v, a.m, b.v, b.m)
+    n / n.norm()
 }
 
-fn ball_wall_collision(a: &Ball, w: &Wall) -> (V, V) {
+/// Unit normal pointing from the ball to the closest point of the wall
+pub fn ball_wall_normal(a: &Ball, w: &Wall) -> V {
+    let d = wall_closest_point(w, a.x) - a.x;
+    let l = d.norm();
+    if l > 0.0 {
+        return d / l;
+    }
     let n = cross(w.j, w.k);
-    collision(n, a.v, a.m, w.v, w.m)
-}
-
-pub fn evolve(
-    balls: &mut Vec<Ball>,
-    walls: &mut Vec<Wall>,
-    mut dt: f64,
-) -> (f64, f64) {
-    let mut indices_bb = Vec::new();
-    let mut indices_bw = Vec::new();
-
-    for i in 0..balls.len() {
-        for j in i + 1..balls.len() {
-            let tmp = ball_ball_collision_time(&balls[i], &balls[j]);
-            if tmp < dt {
-                indices_bb.clear();
-                indices_bw.clear();
-            }
-            if tmp <= dt {
-                indices_bb.push((i, j));
-                dt = tmp;
-            }
+    let n = n / n.norm();
+    if dot(a.x - w.x, n) > 0.0 {
+        -n
+    } else {
+        n
+    }
+}
+
+/// New velocities and angular velocities of `a` and `b`
+pub fn ball_ball_collision(a: &Ball, b: &Ball, e: f64, beta: f64) -> ((V, V), (V, V)) {
+    let n = ball_ball_normal(a, b);
+    let g = a.v - b.v + cross(a.r * a.w + b.r * b.w, n);
+    let j = impulse(n, g, a.m, b.m, rotational(a) + rotational(b), e, beta);
+    (
+        (a.v + j / a.m, spin(a, n, j)),
+        (b.v - j / b.m, spin(b, -n, -j)),
+    )
+}
+
+/// New velocity and angular velocity of `a` and new velocity of `w`
+pub fn ball_wall_collision(a: &Ball, w: &Wall, e: f64, beta: f64) -> ((V, V), V) {
+    let n = ball_wall_normal(a, w);
+    let g = a.v - w.v + cross(a.r * a.w, n);
+    let j = impulse(n, g, a.m, w.m, rotational(a), e, beta);
+    ((a.v + j / a.m, spin(a, n, j)), w.v - j / w.m)
+}
+
+/// New velocities of `a` and `b` when their distance reaches a step of height `du` of the potential,
+/// and whether they went through the step or bounced on it
+pub fn ball_ball_step(a: &Ball, b: &Ball, du: f64) -> (V, V, bool) {
+    let n = ball_ball_normal(a, b);
+    let mu = 1.0 / (1.0 / a.m + 1.0 / b.m);
+    let vn = dot(b.v - a.v, n);
+    let k = vn * vn - 2.0 * du / mu;
+    let (dvn, passed) = if k > 0.0 {
+        (vn.signum() * k.sqrt() - vn, true)
+    } else {
+        (-2.0 * vn, false)
+    };
+    // impulse received by `b`
+    let j = mu * dvn * n;
+    (a.v - j / a.m, b.v + j / b.m, passed)
+}
+
+/// Velocity of `a` re-emitted by the thermal wall `w` at temperature `t`,
+/// sampled from the Maxwell-Boltzmann flux through the wall
+pub fn ball_thermal_wall_collision(a: &Ball, w: &Wall, t: f64, rng: &mut Rng) -> V {
+    let n = -ball_wall_normal(a, w);
+    // tangent to the contact, `j` may not be for the edges and corners
+    let mut t1 = w.j - dot(w.j, n) * n;
+    if t1.norm() < 1e-9 * w.j.norm() {
+        t1 = w.k - dot(w.k, n) * n;
+    }
+    let t1 = t1 / t1.norm();
+    let t2 = cross(n, t1);
+    let s = (t / a.m).sqrt();
+    let vn = s * (-2.0 * rng.uniform().ln()).sqrt();
+    w.v + vn * n + s * rng.normal() * t1 + s * rng.normal() * t2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(x: V, v: V, m: f64) -> Ball {
+        Ball {
+            x,
+            v,
+            a: V::default(),
+            w: V::default(),
+            m,
+            r: 0.5,
+            i: 0.0,
+            e: None,
+            s: 0,
         }
-        for j in 0..walls.len() {
-            let tmp = ball_wall_collision_time(&balls[i], &walls[j]);
-            if tmp < dt {
-                indices_bb.clear();
-                indices_bw.clear();
-            }
-            if tmp <= dt {
-                indices_bw.push((i, j));
-                dt = tmp;
-            }
+    }
+
+    #[test]
+    fn inelastic_collision() {
+        let a = ball(V::new(0.0, 0.0, 0.0), V::new(1.0, 0.2, 0.0), 1.0);
+        let b = ball(V::new(1.0, 0.0, 0.0), V::new(-0.5, 0.0, 0.3), 2.0);
+        for &e in &[0.0, 0.5, 1.0] {
+            let ((va, _), (vb, _)) = ball_ball_collision(&a, &b, e, -1.0);
+            let p = a.m * va + b.m * vb - a.m * a.v - b.m * b.v;
+            assert!(p.norm() < 1e-12);
+            // the normal relative velocity is reversed and scaled by `e`, the rest is untouched
+            assert!((vb.0 - va.0 + e * (b.v.0 - a.v.0)).abs() < 1e-12);
+            assert!((va.1 - a.v.1).abs() < 1e-12 && (vb.2 - b.v.2).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rough_collision() {
+        let mut a = ball(V::new(0.0, 0.0, 0.0), V::new(1.0, 0.3, -0.2), 1.0);
+        let mut b = ball(V::new(0.6, 0.8, 0.0), V::new(-0.5, 0.1, 0.4), 2.0);
+        a.i = 0.4 * a.m * a.r * a.r;
+        b.i = 0.4 * b.m * b.r * b.r;
+        a.w = V::new(0.5, -1.0, 2.0);
+        b.w = V::new(-0.3, 0.7, 0.1);
+        let energy = |a: &Ball, b: &Ball| {
+            a.m * dot(a.v, a.v) + a.i * dot(a.w, a.w) + b.m * dot(b.v, b.v) + b.i * dot(b.w, b.w)
+        };
+        let momentum = |a: &Ball, b: &Ball| {
+            a.m * cross(a.x, a.v) + a.i * a.w + b.m * cross(b.x, b.v) + b.i * b.w
+        };
+        let ((va, wa), (vb, wb)) = ball_ball_collision(&a, &b, 1.0, 1.0);
+        let (mut a1, mut b1) = (a.clone(), b.clone());
+        a1.v = va;
+        a1.w = wa;
+        b1.v = vb;
+        b1.w = wb;
+        // perfectly rough and elastic, the tangential velocity of the contact points is reversed
+        assert!((energy(&a1, &b1) - energy(&a, &b)).abs() < 1e-12);
+        assert!((momentum(&a1, &b1) - momentum(&a, &b)).norm() < 1e-12);
+        let n = ball_ball_normal(&a, &b);
+        let g = |a: &Ball, b: &Ball| {
+            let g = a.v - b.v + cross(a.r * a.w + b.r * b.w, n);
+            g - dot(g, n) * n
+        };
+        assert!((g(&a1, &b1) + g(&a, &b)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn steps_of_the_potential() {
+        let a = ball(V::new(0.0, 0.0, 0.0), V::new(0.5, 0.1, 0.0), 1.0);
+        let b = ball(V::new(1.5, 0.0, 0.0), V::new(-0.5, 0.0, 0.0), 3.0);
+        let energy = |va: V, vb: V| 0.5 * a.m * dot(va, va) + 0.5 * b.m * dot(vb, vb);
+        let e0 = energy(a.v, b.v);
+        // falling into a well, climbing out of it and bouncing on a step too high
+        for &du in &[-0.3, 0.2, 1.0] {
+            let (va, vb, passed) = ball_ball_step(&a, &b, du);
+            assert_eq!(passed, du < 1.0);
+            let du = if passed { du } else { 0.0 };
+            assert!((energy(va, vb) + du - e0).abs() < 1e-12);
+            assert!((a.m * va + b.m * vb - a.m * a.v - b.m * b.v).norm() < 1e-12);
         }
     }
 
-    if dt > 0.0 {
-        for a in balls.iter_mut() {
-            a.x += a.v * dt;
+    #[test]
+    fn thermal_wall() {
+        let w = Wall {
+            x: V::new(-1.0, -1.0, 0.0),
+            v: V::default(),
+            j: V::new(2.0, 0.0, 0.0),
+            k: V::new(0.0, 2.0, 0.0),
+            m: f64::INFINITY,
+            e: None,
+            temperature: Some(2.0),
+            motion: None,
+            shape: Shape::Parallelogram,
+        };
+        let a = ball(V::new(0.0, 0.0, 0.5), V::new(0.3, 0.0, -1.0), 4.0);
+        let mut rng = Rng::new(3);
+        let n = 100_000;
+        let (mut normal, mut tangent) = (0.0, 0.0);
+        for _ in 0..n {
+            let v = ball_thermal_wall_collision(&a, &w, 2.0, &mut rng);
+            assert!(v.2 > 0.0);
+            normal += a.m * v.2 * v.2;
+            tangent += 0.5 * a.m * (v.0 * v.0 + v.1 * v.1);
         }
-        for w in walls.iter_mut() {
-            w.x += w.v * dt;
+        // the flux through the wall has twice the mean squared normal velocity of the gas
+        assert!((normal / n as f64 - 4.0).abs() < 0.05);
+        assert!((tangent / n as f64 - 2.0).abs() < 0.05);
+    }
+
+    fn square() -> Wall {
+        Wall {
+            x: V::new(0.0, 0.0, 0.0),
+            v: V::default(),
+            j: V::new(1.0, 0.0, 0.0),
+            k: V::new(0.0, 1.0, 0.0),
+            m: f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
         }
     }
 
-    let mut work = 0.0;
-    for (i, j) in indices_bb {
-        let (va, vb) = ball_ball_collision(&balls[i], &balls[j]);
-        balls[i].v = va;
-        balls[j].v = vb;
+    #[test]
+    fn faces_edges_and_corners() {
+        let w = square();
+        let time = |x: V, v: V| ball_wall_collision_time(&ball(x, v, 1.0), V::default(), &w);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // the face, an edge and a corner
+        assert!(close(
+            time(V::new(0.5, 0.5, 2.0), V::new(0.0, 0.0, -1.0)),
+            1.5
+        ));
+        assert!(close(
+            time(V::new(-2.0, 0.5, 0.0), V::new(1.0, 0.0, 0.0)),
+            1.5
+        ));
+        let d = V::new(1.0, 1.0, 0.0) / 2f64.sqrt();
+        assert!(close(time(-2.0 * d, d), 1.5));
+        // passing by
+        assert_eq!(
+            time(V::new(-2.0, -0.6, 0.0), V::new(1.0, 0.0, 0.0)),
+            f64::INFINITY
+        );
+        assert_eq!(
+            time(V::new(0.5, 0.5, 2.0), V::new(0.0, 0.0, 1.0)),
+            f64::INFINITY
+        );
+        // bouncing back from the edge along the normal to it
+        let a = ball(V::new(-0.5, 0.5, 0.0), V::new(1.0, 0.0, 0.0), 1.0);
+        assert_eq!(ball_wall_normal(&a, &w), V::new(1.0, 0.0, 0.0));
+        let ((v, _), _) = ball_wall_collision(&a, &w, 1.0, -1.0);
+        assert!((v - V::new(-1.0, 0.0, 0.0)).norm() < 1e-12);
     }
-    for (i, j) in indices_bw {
-        let a = &mut balls[i];
-        let w = &mut walls[j];
-        let (va, vw) = ball_wall_collision(a, w);
-        work = dot(a.m * (va - a.v), w.v);
-        a.v = va;
-        w.v = vw;
+
+    #[test]
+    fn closest_point() {
+        let w = square();
+        assert_eq!(
+            wall_closest_point(&w, V::new(0.3, 0.6, 2.0)),
+            V::new(0.3, 0.6, 0.0)
+        );
+        assert_eq!(
+            wall_closest_point(&w, V::new(-1.0, 0.6, 2.0)),
+            V::new(0.0, 0.6, 0.0)
+        );
+        assert_eq!(
+            wall_closest_point(&w, V::new(2.0, 3.0, -1.0)),
+            V::new(1.0, 1.0, 0.0)
+        );
     }
 
-    (dt, work)
+    #[test]
+    fn polygons() {
+        let (x, j, k, shape) = polygon(&[
+            V::new(1.0, 0.0, 0.0),
+            V::new(3.0, 0.0, 0.0),
+            V::new(1.0, 2.0, 0.0),
+        ]);
+        let mut w = Wall {
+            x,
+            j,
+            k,
+            shape,
+            ..square()
+        };
+        assert!(matches!(w.shape, Shape::Triangle));
+        assert!((w.area() - 2.0).abs() < 1e-12);
+        assert_eq!(
+            wall_closest_point(&w, V::new(2.5, 1.5, 1.0)),
+            V::new(2.0, 1.0, 0.0)
+        );
+
+        // a regular hexagon of side 1 in the plane z = 1
+        let corners: Vec<V> = (0..6)
+            .map(|i| {
+                let a = std::f64::consts::PI * i as f64 / 3.0;
+                V::new(a.cos(), a.sin(), 1.0)
+            })
+            .collect();
+        let (x, j, k, shape) = polygon(&corners);
+        w = Wall {
+            x,
+            j,
+            k,
+            shape,
+            ..square()
+        };
+        assert!((w.area() - 1.5 * 3f64.sqrt()).abs() < 1e-12);
+        for (c, d) in w.corners().iter().zip(&corners) {
+            assert!((w.x + *c - *d).norm() < 1e-12);
+        }
+        let time = |x: V| {
+            ball_wall_collision_time(&ball(x, V::new(0.0, 0.0, -1.0), 1.0), V::default(), &w)
+        };
+        assert!((time(V::new(0.0, 0.0, 3.0)) - 1.5).abs() < 1e-12);
+        // beyond a side, further than the radius
+        assert_eq!(time(V::new(1.2, 1.2, 3.0)), f64::INFINITY);
+    }
+
+    #[test]
+    fn containers() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let sphere = Container::Sphere {
+            center: V::new(1.0, 0.0, 0.0),
+            radius: 3.0,
+        };
+        let a = ball(V::new(1.0, 0.0, 0.0), V::new(0.0, 2.0, 0.0), 1.0);
+        assert!(close(
+            ball_container_collision_time(&a, V::default(), &sphere),
+            1.25
+        ));
+        // thrown up, it falls back at the bottom
+        let g = V::new(0.0, 0.0, -1.0);
+        let a = ball(V::new(1.0, 0.0, 0.0), V::new(0.0, 0.0, 1.0), 1.0);
+        let t = ball_container_collision_time(&a, g, &sphere);
+        assert!(close(1.0 * t - 0.5 * t * t, -2.5));
+
+        let cylinder = Container::Cylinder {
+            base: V::new(0.0, 0.0, -1.0),
+            axis: V::new(0.0, 0.0, 4.0),
+            radius: 2.0,
+        };
+        let a = ball(V::new(0.0, 0.0, 0.0), V::new(1.0, 0.0, 0.0), 1.0);
+        assert!(close(
+            ball_container_collision_time(&a, V::default(), &cylinder),
+            1.5
+        ));
+        assert!(close(container_clearance(a.x, &cylinder), 1.0));
+        let a = ball(V::new(0.0, 0.0, 0.0), V::new(0.0, 0.0, 1.0), 1.0);
+        assert!(close(
+            ball_container_collision_time(&a, V::default(), &cylinder),
+            2.5
+        ));
+        // the cap is hit, the ball goes back
+        let a = ball(V::new(0.0, 0.0, 2.5), V::new(0.3, 0.0, 1.0), 1.0);
+        assert_eq!(ball_container_normal(&a, &cylinder), V::new(0.0, 0.0, 1.0));
+        let (v, _) = ball_container_collision(&a, &cylinder, 1.0, -1.0);
+        assert!((v - V::new(0.3, 0.0, -1.0)).norm() < 1e-12);
+    }
 }
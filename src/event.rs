@@ -0,0 +1,98 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::vec3::V;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    BallBall(usize, usize),
+    BallWall(usize, usize),
+    BallContainer(usize, usize),
+    // two balls crossing a step of the potential, inward (-1) or outward (+1)
+    Step(usize, usize, isize),
+    // two bonded balls reaching the minimum (-1) or maximum (+1) length of the bond
+    Bond(usize, usize, isize),
+    // ball, axis and direction of the crossing of a cell boundary
+    Crossing(usize, usize, isize),
+    // ball and moving wall for which the prediction has to be carried on
+    Recheck(usize, usize),
+    Thermostat,
+}
+
+/// Collision that was processed, as seen from the first ball
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    pub t: f64,
+    pub kind: EventKind,
+    // unit vector from the ball towards the contact
+    pub normal: V,
+    // change of momentum of the ball
+    pub impulse: V,
+}
+
+/// How a step of the simulation ended
+#[derive(Debug, Clone, Copy)]
+pub enum Step {
+    Collision(Record),
+    // no collision up to the end of the step
+    Quiet,
+    // too many events without a collision, stopped before the end
+    GaveUp,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub t: f64,
+    pub kind: EventKind,
+    // collision counters of the two partners at the time of the prediction
+    pub n: (u64, u64),
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Event) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Event) -> Ordering {
+        // reversed such that the earliest event is on top of the heap
+        other.t.total_cmp(&self.t)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EventQueue {
+    heap: BinaryHeap<Event>,
+}
+
+impl EventQueue {
+    pub fn push(&mut self, event: Event) {
+        if event.t.is_finite() {
+            self.heap.push(event);
+        }
+    }
+    pub fn pop(&mut self) -> Option<Event> {
+        self.heap.pop()
+    }
+    pub fn next_time(&self) -> f64 {
+        self.heap.peek().map_or(f64::INFINITY, |e| e.t)
+    }
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+    pub fn clear(&mut self) {
+        self.heap.clear();
+    }
+    pub fn retain<F: FnMut(&Event) -> bool>(&mut self, f: F) {
+        self.heap.retain(f);
+    }
+}
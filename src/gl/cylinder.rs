@@ -0,0 +1,73 @@
+use glium;
+
+use std::f32;
+
+#[derive(Copy, Clone)]
+pub struct Vertex {
+    position: (f32, f32, f32),
+    normal: (f32, f32, f32),
+}
+
+implement_vertex!(Vertex, position, normal);
+
+/// Open cylinder of radius 1 around the z axis, from z = 0 to z = 1
+pub struct Cylinder {
+    positions: glium::VertexBuffer<Vertex>,
+    indices: glium::IndexBuffer<u16>,
+}
+
+impl Cylinder {
+    pub fn new<F>(facade: &F, slices: u16) -> Cylinder
+    where
+        F: glium::backend::Facade,
+    {
+        let mut positions = Vec::with_capacity(2 * slices as usize);
+
+        let beta = 2.0 * f32::consts::PI / slices as f32;
+
+        for j in 0..slices {
+            let j = j as f32;
+            let x = (j * beta).cos();
+            let y = (j * beta).sin();
+            for &z in &[0.0, 1.0] {
+                positions.push(Vertex {
+                    position: (x, y, z),
+                    normal: (x, y, 0.0),
+                });
+            }
+        }
+
+        let mut indices: Vec<u16> = Vec::with_capacity(6 * slices as usize);
+
+        for j in 0..slices {
+            // bottom and top of this slice and of the next one
+            let (b0, t0) = (2 * j, 2 * j + 1);
+            let (b1, t1) = (2 * ((j + 1) % slices), 2 * ((j + 1) % slices) + 1);
+
+            indices.push(b0);
+            indices.push(b1);
+            indices.push(t1);
+
+            indices.push(b0);
+            indices.push(t1);
+            indices.push(t0);
+        }
+
+        Cylinder {
+            positions: glium::VertexBuffer::new(facade, &positions).unwrap(),
+            indices: glium::IndexBuffer::new(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &indices,
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn get_positions(&self) -> &glium::VertexBuffer<Vertex> {
+        &self.positions
+    }
+    pub fn get_indices(&self) -> &glium::IndexBuffer<u16> {
+        &self.indices
+    }
+}
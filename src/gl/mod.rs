@@ -2,3 +2,4 @@ pub mod math;
 pub mod sphere;
 pub mod window;
 pub mod camera;
+pub mod cylinder;
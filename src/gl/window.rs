@@ -1,5 +1,8 @@
 use crate::dynamics::{Ball, Wall};
+use crate::periodic::Periodic;
+use crate::vec3::{cross, V};
 use crate::gl::camera::Camera;
+use crate::gl::cylinder::Cylinder;
 use crate::gl::math::Mat4;
 use crate::gl::sphere::Sphere;
 use glium::glutin;
@@ -13,9 +16,11 @@ struct Vertex {
 
 implement_vertex!(Vertex, position, normal);
 
-pub fn visualize<F>(mut evolution: F) -> !
+/// `bonds` are the pairs of balls drawn linked by a cylinder, along the minimum image if `periodic`,
+/// `evolution` gives the simulation time, the balls and the walls after some seconds
+pub fn visualize<F>(bonds: Vec<(usize, usize)>, periodic: Option<Periodic>, mut evolution: F) -> !
 where
-    F: 'static + FnMut() -> (f64, Vec<Ball>, Vec<Wall>),
+    F: 'static + FnMut(f64) -> (f64, Vec<Ball>, Vec<Wall>),
 {
     let event_loop = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new();
@@ -23,26 +28,10 @@ where
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
 
     let sphere = Sphere::new(&display, 30, 30);
+    let cylinder = Cylinder::new(&display, 12);
 
-    let wall_verticies = vec![
-        Vertex {
-            position: [0.0, 0.0, 0.0, 1.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-        Vertex {
-            position: [1.0, 0.0, 0.0, 1.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-        Vertex {
-            position: [1.0, 1.0, 0.0, 1.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-        Vertex {
-            position: [0.0, 1.0, 0.0, 1.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-    ];
-    let wall_verticies = glium::VertexBuffer::new(&display, &wall_verticies).unwrap();
+    // one triangle fan per wall, in the coordinates along `j` and `k`
+    let mut wall_verticies: Vec<glium::VertexBuffer<Vertex>> = Vec::new();
     let wall_indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
 
     let vertex_sphere = r#"
@@ -133,13 +122,8 @@ where
         glium::Program::from_source(&display, vertex_wall, fragment_wall, None).unwrap();
 
     let start = std::time::Instant::now();
-    let mut t_next = 0.0;
-    let mut dt = 0.0;
     let mut last = start;
 
-    let mut balls = Vec::new();
-    let mut walls = Vec::new();
-
     let mut camera = Camera::new(Mat4::translation(0.0, 0.0, -6.0));
 
     event_loop.run(move |event, _, control_flow| {
@@ -166,19 +150,7 @@ where
 
         last = std::time::Instant::now();
 
-        let delta_t = {
-            let t = (std::time::Instant::now() - start).as_secs_f64();
-
-            while t > t_next {
-                let (new_dt, new_balls, new_walls) = evolution();
-                t_next += new_dt;
-                dt = new_dt;
-                balls = new_balls;
-                walls = new_walls;
-            }
-
-            t - (t_next - dt)
-        };
+        let (time, balls, walls) = evolution((last - start).as_secs_f64());
 
         let mut target = display.draw();
         target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
@@ -205,7 +177,7 @@ where
         for i in 0..balls.len() {
             let a = &balls[i];
             let r = a.r;
-            let x = a.x + delta_t * a.v;
+            let x = a.x;
 
             let model =
                 Mat4::translation(x.0 as f32, x.1 as f32, x.2 as f32) * Mat4::scale(r as f32);
@@ -230,9 +202,66 @@ where
                 .unwrap();
         }
 
-        // draw walls
+        // draw bonds
+        for &(i, k) in &bonds {
+            let (a, b) = (&balls[i], &balls[k]);
+            let d = match &periodic {
+                Some(p) => p.image_at(b.x - a.x, time).0,
+                None => b.x - a.x,
+            };
+            let l = d.norm();
+            if l == 0.0 {
+                continue;
+            }
+            // two unit vectors orthogonal to the bond, scaled to the radius of the cylinder
+            let u = d / l;
+            let e = if u.0.abs() < 0.9 { V::new(1.0, 0.0, 0.0) } else { V::new(0.0, 1.0, 0.0) };
+            let e1 = cross(u, e);
+            let e1 = e1 / e1.norm();
+            let e2 = cross(u, e1);
+            let r = 0.3 * a.r.min(b.r);
+            let (e1, e2) = (r * e1, r * e2);
+            let m = Mat4::from_array([
+                [e1.0 as f32, e1.1 as f32, e1.2 as f32, 0.0],
+                [e2.0 as f32, e2.1 as f32, e2.2 as f32, 0.0],
+                [d.0 as f32, d.1 as f32, d.2 as f32, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]);
+
+            let uniform = uniform! {
+                model: (Mat4::translation(a.x.0 as f32, a.x.1 as f32, a.x.2 as f32) * m).as_array(),
+                view: camera.view.as_array(),
+                perspective: pers.as_array(),
+                light: [0., 0., -3f32],
+                high_color: [0.8, 0.8, 0.3f32],
+                dark_color: [0.4, 0.4, 0.1f32],
+            };
+
+            target
+                .draw(
+                    cylinder.get_positions(),
+                    cylinder.get_indices(),
+                    &program_sphere,
+                    &uniform,
+                    &params,
+                )
+                .unwrap();
+        }
+
+        // draw walls, their shape does not change
+        for w in &walls[wall_verticies.len()..] {
+            let outline: Vec<Vertex> = w
+                .outline()
+                .iter()
+                .map(|&(u, v)| Vertex {
+                    position: [u as f32, v as f32, 0.0, 1.0],
+                    normal: [0.0, 0.0, 1.0],
+                })
+                .collect();
+            wall_verticies.push(glium::VertexBuffer::new(&display, &outline).unwrap());
+        }
         for (i, w) in walls.iter().enumerate() {
-            let x = w.x + delta_t * w.v;
+            let x = w.x;
             let m = Mat4::from_array([
                 [w.j.0 as f32, w.j.1 as f32, w.j.2 as f32, 0.0],
                 [w.k.0 as f32, w.k.1 as f32, w.k.2 as f32, 0.0],
@@ -276,7 +305,7 @@ where
 
             target
                 .draw(
-                    &wall_verticies,
+                    &wall_verticies[i],
                     &wall_indices,
                     &program_wall,
                     &uniform,
@@ -1,18 +1,48 @@
 #[macro_use]
 extern crate glium;
 
+mod cells;
+mod collisions;
+mod diagnostics;
+mod distribution;
 mod dynamics;
+mod event;
 mod gl;
+mod mesh;
+mod motion;
+mod periodic;
+mod poly;
+mod rng;
+mod simulation;
+mod structure;
+mod thermostat;
+mod transport;
+mod validation;
 mod vec3;
 
 use core;
-use dynamics::{evolve, Ball, Wall};
-use vec3::V;
+use collisions::enskog;
+use diagnostics::Monitor;
+use event::{EventKind, Record, Step};
+use distribution::{histogram, kl_divergence, ks_statistic, Maxwell};
+use dynamics::{polygon, Ball, Container, Interaction, Potential, Shape, Wall};
+use motion::Motion;
+use periodic::Periodic;
+use simulation::Simulation;
+use structure::{Rdf, Region, StructureFactor};
+use thermostat::Thermostat;
+use transport::Correlations;
+use validation::{
+    ball_problems, bond_problems, container_problems, polygon_problems, wall_problems, Problem,
+};
+use vec3::{cross, V};
 
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::{
     pyclass, pyfunction, pymethods, pymodule, PyModule, PyResult, Python,
 };
 use pyo3::wrap_pyfunction;
+use std::collections::HashMap;
 
 #[pymodule]
 fn gas(_py: Python, m: &PyModule) -> PyResult<()> {
@@ -21,11 +51,52 @@ fn gas(_py: Python, m: &PyModule) -> PyResult<()> {
     Ok(())
 }
 
+// the problems as a Python exception, if any
+fn check(problems: Vec<Problem>) -> PyResult<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let mut lines: Vec<String> = problems.iter().take(20).map(|p| p.to_string()).collect();
+    if problems.len() > 20 {
+        lines.push(format!("and {} more", problems.len() - 20));
+    }
+    Err(PyValueError::new_err(lines.join("\n")))
+}
+
+type EventTuple = (f64, &'static str, usize, usize, (f64, f64, f64), (f64, f64, f64));
+
+fn event_tuple(r: &Record) -> EventTuple {
+    let (kind, i, j) = match r.kind {
+        EventKind::BallBall(i, k) => ("ball-ball", i, k),
+        EventKind::BallWall(i, j) => ("ball-wall", i, j),
+        EventKind::BallContainer(i, c) => ("ball-container", i, c),
+        EventKind::Step(i, k, _) => ("step", i, k),
+        EventKind::Bond(i, k, _) => ("bond", i, k),
+        _ => ("other", 0, 0),
+    };
+    let (n, p) = (r.normal, r.impulse);
+    (r.t, kind, i, j, (n.0, n.1, n.2), (p.0, p.1, p.2))
+}
+
+// histogram of `x` as the edges and the values of the bins
+fn binned(x: &[f64], bins: usize, range: (f64, f64)) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    if x.is_empty() || bins == 0 || range.0.is_nan() || range.1.is_nan() || range.0 >= range.1 {
+        return Err(PyValueError::new_err("no balls, no bins or an empty range"));
+    }
+    let w = (range.1 - range.0) / bins as f64;
+    let edges = (0..=bins).map(|b| range.0 + b as f64 * w).collect();
+    Ok((edges, histogram(x, bins, range.0, range.1)))
+}
+
 #[pyclass]
 #[derive(Default)]
 pub struct State {
-    balls: Vec<Ball>,
-    walls: Vec<Wall>,
+    sim: Simulation,
+    monitor: Option<Monitor>,
+    // accumulated over the samples
+    rdf: Option<Rdf>,
+    structure_factor: Option<StructureFactor>,
+    correlations: Option<Correlations>,
 }
 
 #[pymethods]
@@ -35,15 +106,45 @@ impl State {
         State::default()
     }
 
-    fn add_ball(&mut self, position: (f64, f64, f64), speed: (f64, f64, f64), m: f64, r: f64) {
-        self.balls.push(Ball {
+    #[args(
+        acceleration = "(0.0, 0.0, 0.0)",
+        restitution = "None",
+        angular_velocity = "(0.0, 0.0, 0.0)",
+        inertia = "None",
+        species = "0"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn add_ball(
+        &mut self,
+        position: (f64, f64, f64),
+        speed: (f64, f64, f64),
+        m: f64,
+        r: f64,
+        acceleration: (f64, f64, f64),
+        restitution: Option<f64>,
+        angular_velocity: (f64, f64, f64),
+        inertia: Option<f64>,
+        species: usize,
+    ) -> PyResult<()> {
+        let a = Ball {
             x: V::new(position.0, position.1, position.2),
             v: V::new(speed.0, speed.1, speed.2),
+            a: V::new(acceleration.0, acceleration.1, acceleration.2),
+            w: V::new(angular_velocity.0, angular_velocity.1, angular_velocity.2),
             m: m,
             r: r,
-        })
+            // solid sphere by default
+            i: inertia.unwrap_or(0.4 * m * r * r),
+            e: restitution,
+            s: species,
+        };
+        check(ball_problems(self.sim.ball_count(), &a))?;
+        self.sim.add_ball(a);
+        Ok(())
     }
 
+    #[args(restitution = "None", temperature = "None")]
+    #[allow(clippy::too_many_arguments)]
     fn add_wall(
         &mut self,
         position: (f64, f64, f64),
@@ -51,55 +152,805 @@ impl State {
         j: (f64, f64, f64),
         k: (f64, f64, f64),
         m: f64,
-    ) {
-        self.walls.push(Wall {
+        restitution: Option<f64>,
+        temperature: Option<f64>,
+    ) -> PyResult<()> {
+        let w = Wall {
             x: V::new(position.0, position.1, position.2),
             v: V::new(speed.0, speed.1, speed.2),
             j: V::new(j.0, j.1, j.2),
             k: V::new(k.0, k.1, k.2),
             m: m,
+            e: restitution,
+            temperature,
+            motion: None,
+            shape: Shape::Parallelogram,
+        };
+        check(wall_problems(self.sim.wall_count(), &w))?;
+        self.sim.add_wall(w);
+        Ok(())
+    }
+
+    /// Flat convex polygon, a triangle if there are three corners
+    #[args(restitution = "None", temperature = "None")]
+    fn add_polygon(
+        &mut self,
+        corners: Vec<(f64, f64, f64)>,
+        speed: (f64, f64, f64),
+        m: f64,
+        restitution: Option<f64>,
+        temperature: Option<f64>,
+    ) -> PyResult<()> {
+        let corners: Vec<V> = corners.iter().map(|c| V::new(c.0, c.1, c.2)).collect();
+        check(polygon_problems(self.sim.wall_count(), &corners))?;
+        let (x, j, k, shape) = polygon(&corners);
+        let w = Wall {
+            x,
+            v: V::new(speed.0, speed.1, speed.2),
+            j,
+            k,
+            m,
+            e: restitution,
+            temperature,
+            motion: None,
+            shape,
+        };
+        check(wall_problems(self.sim.wall_count(), &w))?;
+        self.sim.add_wall(w);
+        Ok(())
+    }
+
+    /// Add the triangles of an STL or OBJ file as fixed walls, returns their number.
+    /// The mesh has to be closed unless `closed` is false.
+    #[args(scale = "1.0", restitution = "None", temperature = "None", closed = "true")]
+    fn load_mesh(
+        &mut self,
+        path: &str,
+        scale: f64,
+        restitution: Option<f64>,
+        temperature: Option<f64>,
+        closed: bool,
+    ) -> PyResult<usize> {
+        let triangles = mesh::load(path)?;
+        let open = mesh::open_edges(&triangles);
+        if closed && open > 0 {
+            return Err(PyValueError::new_err(format!(
+                "{}: the mesh is not closed, {} edges are not shared by two triangles",
+                path, open
+            )));
+        }
+        let mut walls = Vec::new();
+        for t in &triangles {
+            let (x, j, k, shape) = polygon(&[scale * t[0], scale * t[1], scale * t[2]]);
+            if cross(j, k).norm() == 0.0 {
+                // degenerate, nothing to hit
+                continue;
+            }
+            walls.push(Wall {
+                x,
+                v: V::new(0.0, 0.0, 0.0),
+                j,
+                k,
+                m: f64::INFINITY,
+                e: restitution,
+                temperature,
+                motion: None,
+                shape,
+            });
+        }
+        let n = self.sim.wall_count();
+        check(walls.iter().enumerate().flat_map(|(j, w)| wall_problems(n + j, w)).collect())?;
+        for w in &walls {
+            self.sim.add_wall(w.clone());
+        }
+        Ok(walls.len())
+    }
+
+    /// Tether between the balls `i` and `k`, their distance stays between the two lengths
+    #[args(min_length = "0.0")]
+    fn add_bond(&mut self, i: usize, k: usize, max_length: f64, min_length: f64) -> PyResult<()> {
+        check(bond_problems(i, k, min_length, max_length, self.sim.ball_count()))?;
+        self.sim.add_bond(i, k, min_length, max_length);
+        Ok(())
+    }
+
+    /// Spherical container, the balls have to be inside
+    fn add_sphere_container(&mut self, center: (f64, f64, f64), radius: f64) -> PyResult<()> {
+        let c = Container::Sphere {
+            center: V::new(center.0, center.1, center.2),
+            radius,
+        };
+        check(container_problems(self.sim.container_count(), &c))?;
+        self.sim.add_container(c);
+        Ok(())
+    }
+
+    /// Cylindrical container around the axis from `base` to `base + axis`, closed at both ends
+    fn add_cylinder_container(
+        &mut self,
+        base: (f64, f64, f64),
+        axis: (f64, f64, f64),
+        radius: f64,
+    ) -> PyResult<()> {
+        let c = Container::Cylinder {
+            base: V::new(base.0, base.1, base.2),
+            axis: V::new(axis.0, axis.1, axis.2),
+            radius,
+        };
+        check(container_problems(self.sim.container_count(), &c))?;
+        self.sim.add_container(c);
+        Ok(())
+    }
+
+    /// The wall `j` vibrates as `amplitude * sin(2 pi frequency t + phase)`
+    #[args(phase = "0.0")]
+    fn vibrate_wall(
+        &mut self,
+        j: usize,
+        amplitude: (f64, f64, f64),
+        frequency: f64,
+        phase: f64,
+    ) -> PyResult<()> {
+        self.set_wall_motion(
+            j,
+            Some(Motion::Sine {
+                amplitude: V::new(amplitude.0, amplitude.1, amplitude.2),
+                frequency,
+                phase,
+            }),
+        )
+    }
+
+    /// The wall `j` moves at `velocity` between the times `start` and `end`
+    fn ramp_wall(&mut self, j: usize, velocity: (f64, f64, f64), start: f64, end: f64) -> PyResult<()> {
+        self.set_wall_motion(
+            j,
+            Some(Motion::Ramp {
+                velocity: V::new(velocity.0, velocity.1, velocity.2),
+                start,
+                end,
+            }),
+        )
+    }
+
+    /// The wall `j` goes through the `(time, offset)` keyframes, at constant velocity in between,
+    /// the times have to be distinct
+    fn move_wall(&mut self, j: usize, keyframes: Vec<(f64, (f64, f64, f64))>) -> PyResult<()> {
+        let mut k: Vec<(f64, V)> = keyframes
+            .iter()
+            .map(|&(t, x)| (t, V::new(x.0, x.1, x.2)))
+            .collect();
+        k.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.set_wall_motion(j, Some(Motion::Keyframes(k)))
+    }
+
+    /// The wall `j` goes back to moving freely, with its current velocity
+    fn release_wall(&mut self, j: usize) -> PyResult<()> {
+        self.set_wall_motion(j, None)
+    }
+
+    /// Periodic box centered at the origin. With a `shear` rate, the box is sheared
+    /// along x with the velocity gradient along y (Lees-Edwards boundaries).
+    #[args(periodic = "(true, true, true)", shear = "0.0")]
+    fn set_box(&mut self, lengths: (f64, f64, f64), periodic: (bool, bool, bool), shear: f64) -> PyResult<()> {
+        let l = [lengths.0, lengths.1, lengths.2];
+        if !(l.iter().all(|&l| l.is_finite() && l > 0.0) && shear.is_finite()) {
+            return Err(PyValueError::new_err("the lengths have to be positive and finite, and the shear finite"));
+        }
+        if shear != 0.0 && !(periodic.0 && periodic.1) {
+            return Err(PyValueError::new_err("a sheared box has to be periodic along x and y"));
+        }
+        self.sim.set_periodic(Some(Periodic {
+            l: V::new(lengths.0, lengths.1, lengths.2),
+            p: [periodic.0, periodic.1, periodic.2],
+            shear,
+        }));
+        Ok(())
+    }
+
+    #[args(elastic_speed = "0.0")]
+    fn set_restitution(&mut self, e: f64, elastic_speed: f64) -> PyResult<()> {
+        if !(0.0..=1.0).contains(&e) || elastic_speed.is_nan() || elastic_speed < 0.0 {
+            return Err(PyValueError::new_err(
+                "the coefficient of restitution has to be in [0, 1] and the elastic speed non-negative",
+            ));
+        }
+        self.sim.set_restitution(Some(e), elastic_speed);
+        Ok(())
+    }
+
+    fn set_roughness(&mut self, beta: f64) -> PyResult<()> {
+        if !(-1.0..=1.0).contains(&beta) {
+            return Err(PyValueError::new_err(
+                "the tangential coefficient of restitution has to be in [-1, 1]",
+            ));
+        }
+        self.sim.set_roughness(Some(beta));
+        Ok(())
+    }
+
+    /// Interaction between the balls of species `s1` and `s2`,
+    /// `distance` is the contact distance, the sum of the radii by default
+    #[args(distance = "None", restitution = "None", interact = "true")]
+    fn set_interaction(
+        &mut self,
+        s1: usize,
+        s2: usize,
+        distance: Option<f64>,
+        restitution: Option<f64>,
+        interact: bool,
+    ) -> PyResult<()> {
+        if distance.is_some_and(|d| !(d.is_finite() && d > 0.0)) {
+            return Err(PyValueError::new_err("the contact distance has to be positive and finite"));
+        }
+        if restitution.is_some_and(|e| !(0.0..=1.0).contains(&e)) {
+            return Err(PyValueError::new_err("the coefficient of restitution has to be in [0, 1]"));
+        }
+        self.sim.set_interaction(
+            s1,
+            s2,
+            Interaction {
+                sigma: distance,
+                e: restitution,
+                interact,
+            },
+        );
+        Ok(())
+    }
+
+    /// `steps` is a list of `(lambda, energy)`, the energy applies up to a distance of
+    /// `lambda` times the contact distance, the steps are sorted by increasing `lambda`
+    fn set_potential(&mut self, steps: Vec<(f64, f64)>) -> PyResult<()> {
+        let sorted = steps.windows(2).all(|s| s[0].0 < s[1].0);
+        if !sorted || steps.iter().any(|s| !(s.0.is_finite() && s.1.is_finite() && s.0 > 1.0)) {
+            return Err(PyValueError::new_err(
+                "the steps need finite energies and increasing lambdas above 1",
+            ));
+        }
+        self.sim.set_potential(Potential { steps });
+        Ok(())
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.sim.set_seed(seed)
+    }
+
+    /// Energy given to the balls by each wall at a fixed temperature
+    fn wall_heat(&self) -> Vec<f64> {
+        self.sim.wall_heat().to_vec()
+    }
+
+    fn set_gravity(&mut self, g: (f64, f64, f64)) {
+        self.sim.set_gravity(V::new(g.0, g.1, g.2))
+    }
+
+    /// Andersen thermostat, each ball gets a new velocity at the given rate
+    fn set_andersen_thermostat(&mut self, temperature: f64, rate: f64) -> PyResult<()> {
+        self.set_thermostat(Thermostat::Andersen { temperature, rate })
+    }
+
+    /// The velocities are rescaled to the temperature every period
+    fn set_rescale_thermostat(&mut self, temperature: f64, period: f64) -> PyResult<()> {
+        self.set_thermostat(Thermostat::Rescale { temperature, period })
+    }
+
+    /// Berendsen thermostat with relaxation time `tau`, applied every period
+    fn set_berendsen_thermostat(&mut self, temperature: f64, period: f64, tau: f64) -> PyResult<()> {
+        self.set_thermostat(Thermostat::Berendsen {
+            temperature,
+            period,
+            tau,
         })
     }
 
-    fn visualize(&self) {
-        let mut balls = self.balls.clone();
-        let mut walls = self.walls.clone();
+    fn remove_thermostat(&mut self) {
+        self.sim.set_thermostat(None)
+    }
+
+    /// Raise a ValueError listing the overlaps and the invalid balls, walls, containers and bonds
+    fn validate(&mut self) -> PyResult<()> {
+        check(self.sim.problems())
+    }
+
+    /// Run the simulation for `duration`, returns the work done by the walls.
+    /// The state is validated first if it changed, and checked by the monitor at the end.
+    /// With `samples`, the run is split in as many parts and `sample` is called after each.
+    #[args(samples = "0")]
+    fn run(&mut self, duration: f64, samples: usize) -> PyResult<f64> {
+        if !self.sim.is_ready() {
+            self.validate()?;
+        }
+        let mut work = 0.0;
+        if samples == 0 {
+            work = self.sim.evolve(duration);
+        } else {
+            for _ in 0..samples {
+                work += self.sim.evolve(duration / samples as f64);
+                self.sample();
+            }
+        }
+        if let Some(m) = &mut self.monitor {
+            if let Some(msg) = m.record(self.sim.time(), &self.sim.totals()) {
+                return Err(PyRuntimeError::new_err(msg));
+            }
+        }
+        Ok(work)
+    }
+
+    /// Add the current state to the g(r), S(k) and correlations being measured
+    fn sample(&mut self) {
+        let t = self.sim.time();
+        let x: Vec<V> = self.sim.snapshot().0.iter().map(|a| a.x).collect();
+        if let Some(rdf) = &mut self.rdf {
+            rdf.sample(&x, t);
+        }
+        if let Some(sk) = &mut self.structure_factor {
+            sk.sample(&x);
+        }
+        if let Some(c) = &mut self.correlations {
+            let v = self.sim.peculiar_velocities().iter().map(|(_, v)| *v).collect();
+            c.sample(self.sim.unwrapped_positions(), v, t);
+        }
+    }
+
+    /// Displacement of each ball since `reset_displacements`, or since it was added,
+    /// through the periodic boundaries
+    fn displacements(&mut self) -> Vec<(f64, f64, f64)> {
+        self.sim.displacements().iter().map(|d| (d.0, d.1, d.2)).collect()
+    }
+
+    fn reset_displacements(&mut self) {
+        self.sim.reset_displacements()
+    }
+
+    /// Start measuring the mean squared displacement and the velocity autocorrelation function
+    /// up to `max_lag` samples apart, with every sample as a time origin.
+    /// The samples have to be evenly spaced, as with `run(duration, samples)`.
+    #[args(max_lag = "100")]
+    fn start_correlations(&mut self, max_lag: usize) {
+        self.correlations = Some(Correlations::new(max_lag));
+    }
+
+    /// Lag times and mean squared displacement
+    fn msd(&self) -> PyResult<(Vec<f64>, Vec<f64>)> {
+        let (t, msd, _) = self.correlations()?.result();
+        Ok((t, msd))
+    }
+
+    /// Lag times and velocity autocorrelation function, relative to the flow in a sheared box
+    fn vacf(&self) -> PyResult<(Vec<f64>, Vec<f64>)> {
+        let (t, _, vacf) = self.correlations()?.result();
+        Ok((t, vacf))
+    }
+
+    /// Self-diffusion coefficient from the slope of the mean squared displacement
+    /// and from the integral of the velocity autocorrelation function
+    fn diffusion(&self) -> PyResult<(f64, f64)> {
+        Ok(self.correlations()?.diffusion())
+    }
+
+    /// Start measuring the radial distribution function up to `r_max`, normalized in the periodic
+    /// box, the first container or the axis-aligned box between the corners `lo` and `hi`.
+    /// The container or box is shrunk by the mean radius of the balls, to where their centers can be.
+    #[args(bins = "100", lo = "None", hi = "None")]
+    fn start_rdf(
+        &mut self,
+        r_max: f64,
+        bins: usize,
+        lo: Option<(f64, f64, f64)>,
+        hi: Option<(f64, f64, f64)>,
+    ) -> PyResult<()> {
+        let region = match (lo, hi, self.sim.periodic()) {
+            (Some(lo), Some(hi), _) => Region::Box(V::new(lo.0, lo.1, lo.2), V::new(hi.0, hi.1, hi.2)),
+            (None, None, Some(p)) if p.p.iter().all(|&q| q) => {
+                if 2.0 * r_max > p.l.0.min(p.l.1).min(p.l.2) {
+                    return Err(PyValueError::new_err("r_max is more than half the box"));
+                }
+                Region::Periodic(p)
+            }
+            (None, None, _) if !self.sim.containers().is_empty() => {
+                Region::Container(self.sim.containers()[0].clone())
+            }
+            _ => {
+                return Err(PyValueError::new_err(
+                    "g(r) needs a box periodic along all the axes, a container or both corners of a box",
+                ))
+            }
+        };
+        if bins == 0 || r_max.is_nan() || r_max <= 0.0 {
+            return Err(PyValueError::new_err("no bins or a non-positive r_max"));
+        }
+        let balls = self.sim.snapshot().0;
+        let r = balls.iter().map(|a| a.r).sum::<f64>() / balls.len().max(1) as f64;
+        let region = region.shrink(r).ok_or_else(|| {
+            PyValueError::new_err("the container or box is too small for the balls")
+        })?;
+        self.rdf = Some(Rdf::new(region, r_max, bins));
+        Ok(())
+    }
+
+    /// Centers of the bins and g(r) averaged over the samples
+    fn rdf(&self) -> PyResult<(Vec<f64>, Vec<f64>)> {
+        match &self.rdf {
+            Some(rdf) => Ok(rdf.result()),
+            None => Err(PyValueError::new_err("g(r) is not being measured")),
+        }
+    }
+
+    /// Start measuring the structure factor for the wavevectors `2 pi n / L`
+    /// of the periodic box with `|n_d| <= n_max`
+    #[args(n_max = "10")]
+    fn start_structure_factor(&mut self, n_max: usize) -> PyResult<()> {
+        match self.sim.periodic() {
+            Some(p) if p.p.iter().all(|&q| q) && p.shear == 0.0 => {
+                self.structure_factor = Some(StructureFactor::new(p.l, n_max));
+                Ok(())
+            }
+            _ => Err(PyValueError::new_err(
+                "S(k) needs a box periodic along all the axes and not sheared",
+            )),
+        }
+    }
+
+    /// |k| and S(k) averaged over the samples and over shells of wavevectors
+    fn structure_factor(&self) -> PyResult<(Vec<f64>, Vec<f64>)> {
+        match &self.structure_factor {
+            Some(sk) => Ok(sk.result()),
+            None => Err(PyValueError::new_err("S(k) is not being measured")),
+        }
+    }
+
+    /// Translational and rotational energy of the balls and of the free walls
+    fn kinetic_energy(&mut self) -> f64 {
+        self.sim.totals().kinetic
+    }
+
+    /// Kinetic energy plus the energy of the potential steps and of the accelerations
+    fn energy(&mut self) -> f64 {
+        self.sim.totals().energy()
+    }
+
+    /// Momentum of the balls and of the free walls
+    fn momentum(&mut self) -> (f64, f64, f64) {
+        let p = self.sim.totals().momentum;
+        (p.0, p.1, p.2)
+    }
+
+    /// Angular momentum around the origin, spins included
+    fn angular_momentum(&mut self) -> (f64, f64, f64) {
+        let l = self.sim.totals().angular_momentum;
+        (l.0, l.1, l.2)
+    }
+
+    fn center_of_mass(&mut self) -> (f64, f64, f64) {
+        let x = self.sim.totals().center_of_mass;
+        (x.0, x.1, x.2)
+    }
+
+    /// Instantaneous translational temperature, relative to the flow in a sheared box
+    fn temperature(&mut self) -> f64 {
+        self.sim.temperature()
+    }
+
+    /// Probability density of the component (0, 1 or 2) of the velocities relative to the flow,
+    /// as the edges and the values of the bins. The range covers all the balls by default.
+    #[args(bins = "50", range = "None")]
+    fn velocity_histogram(
+        &mut self,
+        component: usize,
+        bins: usize,
+        range: Option<(f64, f64)>,
+    ) -> PyResult<(Vec<f64>, Vec<f64>)> {
+        if component > 2 {
+            return Err(PyValueError::new_err("the component is 0, 1 or 2"));
+        }
+        let x: Vec<f64> = self.sim.peculiar_velocities().iter().map(|(_, u)| u[component]).collect();
+        let m = x.iter().fold(0.0, |m: f64, x| m.max(x.abs()));
+        binned(&x, bins, range.unwrap_or((-m, m)))
+    }
+
+    /// Probability density of the speeds relative to the flow, as the edges and the values of the bins
+    #[args(bins = "50", range = "None")]
+    fn speed_histogram(&mut self, bins: usize, range: Option<(f64, f64)>) -> PyResult<(Vec<f64>, Vec<f64>)> {
+        let x: Vec<f64> = self.sim.peculiar_velocities().iter().map(|(_, u)| u.norm()).collect();
+        let m = x.iter().fold(0.0, |m: f64, &x| m.max(x));
+        binned(&x, bins, range.unwrap_or((0.0, m)))
+    }
+
+    /// Distance of the distribution of the speeds, or of a component of the velocities, from
+    /// the Maxwell-Boltzmann one at `temperature`, the measured one by default:
+    /// Kolmogorov-Smirnov statistic and Kullback-Leibler divergence of the histogram with `bins` bins
+    #[args(temperature = "None", bins = "50", component = "None")]
+    fn maxwell_comparison(
+        &mut self,
+        temperature: Option<f64>,
+        bins: usize,
+        component: Option<usize>,
+    ) -> PyResult<(f64, f64)> {
+        let u = self.sim.peculiar_velocities();
+        if u.is_empty() || bins == 0 || component.is_some_and(|c| c > 2) {
+            return Err(PyValueError::new_err("no balls, no bins or a component other than 0, 1 or 2"));
+        }
+        let t = temperature.unwrap_or_else(|| self.sim.temperature());
+        if !(t.is_finite() && t > 0.0) {
+            return Err(PyValueError::new_err("the temperature has to be positive and finite"));
+        }
+        let masses: Vec<f64> = u.iter().map(|(m, _)| *m).collect();
+        let mb = Maxwell::new(&masses, t);
+        let (x, lo, hi): (Vec<f64>, f64, f64) = match component {
+            Some(c) => {
+                let x: Vec<f64> = u.iter().map(|(_, u)| u[c]).collect();
+                let m = x.iter().fold(0.0, |m: f64, x| m.max(x.abs()));
+                (x, -m, m)
+            }
+            None => {
+                let x: Vec<f64> = u.iter().map(|(_, u)| u.norm()).collect();
+                let m = x.iter().fold(0.0, |m: f64, &x| m.max(x));
+                (x, 0.0, m)
+            }
+        };
+        if hi <= lo {
+            return Err(PyValueError::new_err("the balls are at rest, there is nothing to bin"));
+        }
+        let cdf = |v: f64| match component {
+            Some(_) => mb.component_cdf(v),
+            None => mb.speed_cdf(v),
+        };
+        Ok((ks_statistic(&x, cdf), kl_divergence(&x, bins, lo, hi, cdf)))
+    }
+
+    /// Log the collisions processed from now on, or stop if `on` is false
+    #[args(on = "true")]
+    fn log_events(&mut self, on: bool) {
+        self.sim.set_log(on)
+    }
+
+    /// Collisions logged since the last call, as `(time, type, i, j, normal, impulse)`,
+    /// see `step`
+    fn events(&mut self) -> Vec<EventTuple> {
+        self.sim.take_log().iter().map(event_tuple).collect()
+    }
+
+    /// Process the events up to the next collision, or for `max_duration` if there is none before,
+    /// returns the collision as `(time, type, i, j, normal, impulse)` or None. The type is
+    /// "ball-ball", "ball-wall", "ball-container", "step" or "bond", `i` is a ball, `j` a ball,
+    /// wall or container, the normal points from `i` to the contact and the impulse is
+    /// the change of momentum of `i`. The search gives up with a RuntimeError after `max_events`
+    /// other events, like the balls crossing the cells, the time is then left where it stopped.
+    #[args(max_duration = "None", max_events = "100000")]
+    fn step(&mut self, max_duration: Option<f64>, max_events: usize) -> PyResult<Option<EventTuple>> {
+        if !self.sim.is_ready() {
+            self.validate()?;
+        }
+        let t_end = self.sim.time() + max_duration.unwrap_or(f64::INFINITY);
+        match self.sim.step(t_end, max_events) {
+            Step::Collision(r) => Ok(Some(event_tuple(&r))),
+            Step::Quiet => Ok(None),
+            Step::GaveUp => Err(PyRuntimeError::new_err(format!(
+                "no collision in {} events, stopped at t = {}",
+                max_events,
+                self.sim.time()
+            ))),
+        }
+    }
+
+    /// Count the collisions and free flights from now on
+    fn reset_collisions(&mut self) {
+        self.sim.reset_collisions()
+    }
+
+    /// Number of collisions of each ball with the other balls and with the walls and containers
+    fn collision_counts(&self) -> (Vec<u64>, Vec<u64>) {
+        let c = self.sim.collisions();
+        (c.ball_ball.clone(), c.ball_wall.clone())
+    }
+
+    /// Totals of the collisions since the reset, frequency of the collisions between balls
+    /// per ball, mean time and length of the free flights between two collisions of a ball
+    fn collision_statistics(&self) -> PyResult<HashMap<&'static str, f64>> {
+        let c = self.sim.collisions();
+        let n = c.ball_ball.len() as f64;
+        let tau = self.sim.time() - c.start;
+        if n == 0.0 || tau <= 0.0 {
+            return Err(PyValueError::new_err("no balls or no time elapsed since the collisions were reset"));
+        }
+        if c.flights == 0 {
+            return Err(PyValueError::new_err("no ball collided twice since the collisions were reset"));
+        }
+        let ball_ball = c.ball_ball.iter().sum::<u64>() as f64;
+        let ball_wall = c.ball_wall.iter().sum::<u64>() as f64;
+        let mut out = HashMap::new();
+        out.insert("ball_ball", 0.5 * ball_ball);
+        out.insert("ball_wall", ball_wall);
+        out.insert("collision_rate", ball_ball / (n * tau));
+        out.insert("mean_free_time", c.flight_time / c.flights as f64);
+        out.insert("mean_free_path", c.flight_distance / c.flights as f64);
+        Ok(out)
+    }
+
+    /// Collision frequency per ball and mean free path predicted by Enskog theory for the
+    /// current temperature, taking all the balls as hard spheres of the mean diameter and mass.
+    /// The volume is the one of the box if it is periodic along all the axes.
+    #[args(volume = "None")]
+    fn enskog(&mut self, volume: Option<f64>) -> PyResult<HashMap<&'static str, f64>> {
+        let volume = match volume.or_else(|| self.sim.box_volume()) {
+            Some(v) => v,
+            None => return Err(PyValueError::new_err("the volume is needed unless the box is periodic")),
+        };
+        let balls = self.sim.snapshot().0;
+        if balls.is_empty() {
+            return Err(PyValueError::new_err("no balls"));
+        }
+        let n = balls.len() as f64;
+        let sigma = balls.iter().map(|a| 2.0 * a.r).sum::<f64>() / n;
+        let m = balls.iter().map(|a| a.m).sum::<f64>() / n;
+        let (frequency, path) = enskog(n / volume, sigma, m, self.sim.temperature());
+        let mut out = HashMap::new();
+        out.insert("collision_rate", frequency);
+        out.insert("mean_free_time", 1.0 / frequency);
+        out.insert("mean_free_path", path);
+        Ok(out)
+    }
+
+    /// Start measuring the pressure, the measures are averages from now on
+    fn reset_pressure(&mut self) {
+        self.sim.reset_pressure()
+    }
+
+    /// Pressure tensor of the balls from their velocities and the collisional virial,
+    /// the volume is the one of the box if it is periodic along all the axes
+    #[args(volume = "None")]
+    fn pressure_tensor(&mut self, volume: Option<f64>) -> PyResult<Vec<(f64, f64, f64)>> {
+        let volume = match volume.or_else(|| self.sim.box_volume()) {
+            Some(v) => v,
+            None => return Err(PyValueError::new_err("the volume is needed unless the box is periodic")),
+        };
+        self.pressure_window()?;
+        let p = self.sim.pressure_tensor(volume);
+        Ok(p.iter().map(|r| (r[0], r[1], r[2])).collect())
+    }
 
-        gl::window::visualize(move || {
-            let old_balls = balls.clone();
-            let old_walls = walls.clone();
+    /// Third of the trace of the pressure tensor
+    #[args(volume = "None")]
+    fn pressure(&mut self, volume: Option<f64>) -> PyResult<f64> {
+        let p = self.pressure_tensor(volume)?;
+        Ok((p[0].0 + p[1].1 + p[2].2) / 3.0)
+    }
 
-            let (dt, _work) = evolve(&mut balls, &mut walls, 10.0);
-            (dt, old_balls, old_walls)
+    /// Average force of the balls on each wall since the pressure was reset
+    fn wall_forces(&self) -> PyResult<Vec<(f64, f64, f64)>> {
+        let tau = self.pressure_window()?;
+        Ok(self
+            .sim
+            .wall_impulses()
+            .iter()
+            .map(|&p| (p.0 / tau, p.1 / tau, p.2 / tau))
+            .collect())
+    }
+
+    /// Average normal force per unit area of the balls on each wall since the pressure was reset
+    fn wall_pressure(&self) -> PyResult<Vec<f64>> {
+        self.pressure_window()?;
+        Ok(self.sim.wall_pressure())
+    }
+
+    /// Record the drift of the conserved quantities from now on at the end of each `run`,
+    /// which raises a RuntimeError when the drift of a checked one exceeds `tolerance`.
+    /// The drifts are relative to the typical magnitudes of the quantities.
+    #[args(energy = "true", momentum = "false", angular_momentum = "false")]
+    fn set_monitor(&mut self, tolerance: f64, energy: bool, momentum: bool, angular_momentum: bool) {
+        let mut m = Monitor::new(self.sim.totals(), tolerance);
+        m.energy = energy;
+        m.momentum = momentum;
+        m.angular_momentum = angular_momentum;
+        self.monitor = Some(m);
+    }
+
+    fn remove_monitor(&mut self) {
+        self.monitor = None
+    }
+
+    /// Drifts recorded by the monitor, as `(time, energy, momentum, angular momentum)`
+    fn drift(&self) -> Vec<(f64, f64, f64, f64)> {
+        self.monitor.as_ref().map_or(Vec::new(), |m| {
+            m.records
+                .iter()
+                .map(|(t, d)| (*t, d.energy, d.momentum, d.angular_momentum))
+                .collect()
+        })
+    }
+
+    #[getter]
+    fn time(&self) -> f64 {
+        self.sim.time()
+    }
+
+    fn visualize(&self) {
+        let mut sim = self.sim.clone();
+        let t0 = sim.time();
+
+        gl::window::visualize(sim.bonds(), sim.periodic(), move |t| {
+            sim.evolve(t0 + t - sim.time());
+            let (balls, walls) = sim.snapshot();
+            (sim.time(), balls, walls)
         });
     }
 }
 
+impl State {
+    fn set_thermostat(&mut self, thermostat: Thermostat) -> PyResult<()> {
+        if !thermostat.is_valid() {
+            return Err(PyValueError::new_err(
+                "the rate, period and tau have to be positive and finite, the temperature non-negative",
+            ));
+        }
+        self.sim.set_thermostat(Some(thermostat));
+        Ok(())
+    }
+
+    fn set_wall_motion(&mut self, j: usize, motion: Option<Motion>) -> PyResult<()> {
+        if j >= self.sim.wall_count() {
+            return Err(PyValueError::new_err(format!("no wall {}", j)));
+        }
+        if motion.as_ref().is_some_and(|m| !m.is_valid()) {
+            return Err(PyValueError::new_err(
+                "infinite or NaN value, a ramp ending before it starts or keyframes not increasing in time",
+            ));
+        }
+        self.sim.set_wall_motion(j, motion);
+        Ok(())
+    }
+
+    fn pressure_window(&self) -> PyResult<f64> {
+        let tau = self.sim.pressure_window();
+        if tau > 0.0 {
+            Ok(tau)
+        } else {
+            Err(PyValueError::new_err("no time elapsed since the pressure was reset"))
+        }
+    }
+
+    fn correlations(&self) -> PyResult<&Correlations> {
+        self.correlations
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("the correlations are not being measured"))
+    }
+}
+
 #[pyfunction]
 fn visualize_example() {
-    let mut balls = Vec::new();
+    let mut sim = Simulation::default();
 
     for &x in &[-2.0, -1.0, 0.0, 1.0, 2.0, 3.0] {
         for &y in &[-2.0, -1.0, 0.0, 1.0, 2.0, 3.0] {
             for &z in &[-2.0, -1.0, 0.0, 1.0, 2.0] {
-                balls.push(Ball {
+                sim.add_ball(Ball {
                     x: V::new(x * 0.201, y * 0.201, z * 0.201),
                     v: V::new(y * 0.1, z * 0.1, x * 0.1),
+                    a: V::new(0.0, 0.0, 0.0),
+                    w: V::new(0.0, 0.0, 0.0),
                     m: 1.0,
                     r: 0.1,
+                    i: 0.4 * 0.1 * 0.1,
+                    e: None,
+                    s: 0,
                 });
             }
         }
     }
 
     let a = 2.0;
-    let mut walls = vec![
+    let walls = vec![
         Wall {
             x: V::new(a, -a, -a),
             v: V::new(0.0, 0.0, 0.0),
             j: V::new(0.0, 2.0 * a, 0.0),
             k: V::new(0.0, 0.0, 2.0 * a),
             m: core::f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
         },
         Wall {
             x: V::new(-a, -a, -a),
@@ -107,6 +958,10 @@ fn visualize_example() {
             j: V::new(0.0, 2.0 * a, 0.0),
             k: V::new(0.0, 0.0, 2.0 * a),
             m: core::f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
         },
         Wall {
             x: V::new(-a, -a, -a),
@@ -114,6 +969,10 @@ fn visualize_example() {
             j: V::new(2.0 * a, 0.0, 0.0),
             k: V::new(0.0, 0.0, 2.0 * a),
             m: core::f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
         },
         Wall {
             x: V::new(-a, a, -a),
@@ -121,6 +980,10 @@ fn visualize_example() {
             j: V::new(2.0 * a, 0.0, 0.0),
             k: V::new(0.0, 0.0, 2.0 * a),
             m: core::f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
         },
         Wall {
             x: V::new(-a, -a, -a),
@@ -128,6 +991,10 @@ fn visualize_example() {
             j: V::new(2.0 * a, 0.0, 0.0),
             k: V::new(0.0, 2.0 * a, 0.0),
             m: core::f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
         },
         Wall {
             x: V::new(-a, -a, a),
@@ -135,26 +1002,20 @@ fn visualize_example() {
             j: V::new(2.0 * a, 0.0, 0.0),
             k: V::new(0.0, 2.0 * a, 0.0),
             m: core::f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
         },
     ];
 
-    gl::window::visualize(move || {
-        // let mut e = 0.0;
-        // for a in &balls {
-        //     e += 0.5 * a.m * a.v.norm().powi(2);
-        // }
-        // println!("energy {:.6}", e);
-
-        // balls = new_balls.clone();
-        // walls = new_walls.clone();
-
-        let old_balls = balls.clone();
-        let old_walls = walls.clone();
-
-        let (dt, _work) = evolve(&mut balls, &mut walls, 10.0);
-
-        // println!("{:.4}", dt);
+    for w in walls {
+        sim.add_wall(w);
+    }
 
-        (dt, old_balls, old_walls)
+    gl::window::visualize(Vec::new(), None, move |t| {
+        sim.evolve(t - sim.time());
+        let (balls, walls) = sim.snapshot();
+        (sim.time(), balls, walls)
     });
 }
@@ -0,0 +1,210 @@
+use crate::vec3::V;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+
+pub type Triangle = [V; 3];
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn number(s: Option<&str>, line: usize) -> Result<f64> {
+    s.and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid(format!("line {}: expected a number", line)))
+}
+
+fn point<'a, I: Iterator<Item = &'a str>>(words: &mut I, line: usize) -> Result<V> {
+    Ok(V::new(
+        number(words.next(), line)?,
+        number(words.next(), line)?,
+        number(words.next(), line)?,
+    ))
+}
+
+/// Triangles of an STL (ASCII or binary) or Wavefront OBJ file, depending on its extension
+pub fn load(path: &str) -> Result<Vec<Triangle>> {
+    let data = std::fs::read(path)?;
+    match path.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+        Some("stl") => {
+            if is_binary_stl(&data) {
+                Ok(parse_binary_stl(&data))
+            } else {
+                parse_ascii_stl(&String::from_utf8_lossy(&data))
+            }
+        }
+        Some("obj") => parse_obj(&String::from_utf8_lossy(&data)),
+        _ => Err(invalid(format!("{}: unknown mesh format", path))),
+    }
+}
+
+// ASCII files can start with 80 bytes of header as well, the size tells them apart
+fn is_binary_stl(data: &[u8]) -> bool {
+    if data.len() < 84 {
+        return false;
+    }
+    let n = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    data.len() == 84 + 50 * n
+}
+
+fn parse_binary_stl(data: &[u8]) -> Vec<Triangle> {
+    let float = |b: &[u8]| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64;
+    let vertex = |b: &[u8]| V::new(float(b), float(&b[4..]), float(&b[8..]));
+    // each facet is a normal, three vertices and two bytes of attributes
+    data[84..]
+        .chunks_exact(50)
+        .map(|c| [vertex(&c[12..]), vertex(&c[24..]), vertex(&c[36..])])
+        .collect()
+}
+
+fn parse_ascii_stl(text: &str) -> Result<Vec<Triangle>> {
+    let mut out = Vec::new();
+    let mut facet = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("vertex") => facet.push(point(&mut words, i + 1)?),
+            Some("endfacet") => {
+                if facet.len() != 3 {
+                    return Err(invalid(format!("line {}: facet without three vertices", i + 1)));
+                }
+                out.push([facet[0], facet[1], facet[2]]);
+                facet.clear();
+            }
+            _ => (),
+        }
+    }
+    Ok(out)
+}
+
+fn parse_obj(text: &str) -> Result<Vec<Triangle>> {
+    let mut vertices = Vec::new();
+    let mut out = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => vertices.push(point(&mut words, i + 1)?),
+            Some("f") => {
+                // `index/texture/normal`, counted from 1 or from the end if negative
+                let mut face = Vec::new();
+                for w in words {
+                    let k: isize = w
+                        .split('/')
+                        .next()
+                        .and_then(|k| k.parse().ok())
+                        .ok_or_else(|| invalid(format!("line {}: expected a vertex index", i + 1)))?;
+                    let k = if k < 0 { vertices.len() as isize + k } else { k - 1 };
+                    match vertices.get(k as usize) {
+                        Some(&v) if k >= 0 => face.push(v),
+                        _ => return Err(invalid(format!("line {}: no vertex {}", i + 1, w))),
+                    }
+                }
+                // split the polygons in triangles around their first vertex
+                for k in 2..face.len() {
+                    out.push([face[0], face[k - 1], face[k]]);
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(out)
+}
+
+/// Number of edges not shared by exactly two triangles, zero if the mesh is closed
+pub fn open_edges(triangles: &[Triangle]) -> usize {
+    let key = |v: V| [v.0.to_bits(), v.1.to_bits(), v.2.to_bits()];
+    let mut edges = HashMap::new();
+    for t in triangles {
+        for k in 0..3 {
+            let (a, b) = (key(t[k]), key(t[(k + 1) % 3]));
+            *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    edges.values().filter(|&&n| n != 2).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron() -> Vec<V> {
+        vec![
+            V::new(0.0, 0.0, 0.0),
+            V::new(1.0, 0.0, 0.0),
+            V::new(0.0, 1.0, 0.0),
+            V::new(0.0, 0.0, 1.0),
+        ]
+    }
+
+    const FACES: [[usize; 3]; 4] = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+
+    #[test]
+    fn ascii_stl() {
+        let v = tetrahedron();
+        let mut text = String::from("solid tetra\n");
+        for f in &FACES {
+            text += "  facet normal 0 0 0\n    outer loop\n";
+            for &k in f {
+                text += &format!("      vertex {} {} {}\n", v[k].0, v[k].1, v[k].2);
+            }
+            text += "    endloop\n  endfacet\n";
+        }
+        text += "endsolid tetra\n";
+        let t = parse_ascii_stl(&text).unwrap();
+        assert_eq!(t.len(), 4);
+        assert_eq!(t[3], [v[1], v[2], v[3]]);
+        assert_eq!(open_edges(&t), 0);
+        assert!(!is_binary_stl(text.as_bytes()));
+
+        let broken = "facet\nvertex 0 0 0\nvertex 1 0 0\nendfacet\n";
+        assert!(parse_ascii_stl(broken).is_err());
+        assert!(parse_ascii_stl("facet\nvertex 0 zero 0\n").is_err());
+    }
+
+    #[test]
+    fn binary_stl() {
+        let v = tetrahedron();
+        let mut data = vec![b' '; 80];
+        data.extend_from_slice(&(FACES.len() as u32).to_le_bytes());
+        for f in &FACES {
+            data.extend_from_slice(&[0; 12]);
+            for &k in f {
+                for x in &[v[k].0, v[k].1, v[k].2] {
+                    data.extend_from_slice(&(*x as f32).to_le_bytes());
+                }
+            }
+            data.extend_from_slice(&[0; 2]);
+        }
+        assert!(is_binary_stl(&data));
+        let t = parse_binary_stl(&data);
+        assert_eq!(t.len(), 4);
+        assert_eq!(t[1], [v[0], v[1], v[3]]);
+        // a missing byte makes it something else
+        assert!(!is_binary_stl(&data[..data.len() - 1]));
+    }
+
+    #[test]
+    fn obj() {
+        let text = "# a square pyramid\n\
+                    v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0.5 0.5 1\n\
+                    vt 0 0\nvn 0 0 1\n\
+                    f 1/1/1 4/1/1 3/1/1 2/1/1\n\
+                    f 1//1 2//1 5//1\n\
+                    f 2 3 5\n\
+                    f -3 -2 -1\n\
+                    f 4 1 5\n";
+        let t = parse_obj(text).unwrap();
+        let v = |x: f64, y: f64, z: f64| V::new(x, y, z);
+        // the square is split around its first vertex
+        assert_eq!(t.len(), 6);
+        assert_eq!(t[0], [v(0.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(1.0, 1.0, 0.0)]);
+        assert_eq!(t[1], [v(0.0, 0.0, 0.0), v(1.0, 1.0, 0.0), v(1.0, 0.0, 0.0)]);
+        // counted from the end
+        assert_eq!(t[4], [v(1.0, 1.0, 0.0), v(0.0, 1.0, 0.0), v(0.5, 0.5, 1.0)]);
+        assert_eq!(open_edges(&t), 0);
+        assert_eq!(open_edges(&t[1..]), 3);
+
+        assert!(parse_obj("v 0 0 0\nf 1 2 3\n").is_err());
+        assert!(parse_obj("v 0 0 0\nf 1 -2 1\n").is_err());
+        assert!(parse_obj("v 0 0 0\nf 0 1 1\n").is_err());
+    }
+}
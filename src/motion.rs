@@ -0,0 +1,189 @@
+use crate::vec3::V;
+
+/// Prescribed trajectory of a wall, given as an offset of its position
+#[derive(Debug, Clone)]
+pub enum Motion {
+    // `amplitude * sin(2 pi frequency t + phase)`
+    Sine {
+        amplitude: V,
+        frequency: f64,
+        phase: f64,
+    },
+    // constant velocity between `start` and `end`, at rest before and after
+    Ramp { velocity: V, start: f64, end: f64 },
+    // linear interpolation between the (time, offset) keyframes sorted by time, at rest outside
+    Keyframes(Vec<(f64, V)>),
+}
+
+impl Motion {
+    /// Whether the values are finite, the ramp ends after it starts
+    /// and the times of the keyframes increase
+    pub fn is_valid(&self) -> bool {
+        let finite = |x: V| x.0.is_finite() && x.1.is_finite() && x.2.is_finite();
+        match self {
+            Motion::Sine {
+                amplitude,
+                frequency,
+                phase,
+            } => finite(*amplitude) && frequency.is_finite() && phase.is_finite(),
+            Motion::Ramp {
+                velocity,
+                start,
+                end,
+            } => finite(*velocity) && start.is_finite() && end.is_finite() && start <= end,
+            Motion::Keyframes(k) => {
+                !k.is_empty()
+                    && k.iter().all(|f| f.0.is_finite() && finite(f.1))
+                    && k.windows(2).all(|w| w[0].0 < w[1].0)
+            }
+        }
+    }
+
+    pub fn offset(&self, t: f64) -> V {
+        match self {
+            Motion::Sine {
+                amplitude,
+                frequency,
+                phase,
+            } => (2.0 * std::f64::consts::PI * frequency * t + phase).sin() * *amplitude,
+            Motion::Ramp {
+                velocity,
+                start,
+                end,
+            } => (t.max(*start).min(*end) - start) * *velocity,
+            Motion::Keyframes(k) => match k.iter().position(|f| f.0 > t) {
+                None => k.last().map_or(V::default(), |f| f.1),
+                Some(0) => k[0].1,
+                Some(i) => {
+                    let (t0, x0) = k[i - 1];
+                    let (t1, x1) = k[i];
+                    x0 + (t - t0) / (t1 - t0) * (x1 - x0)
+                }
+            },
+        }
+    }
+
+    pub fn velocity(&self, t: f64) -> V {
+        match self {
+            Motion::Sine {
+                amplitude,
+                frequency,
+                phase,
+            } => {
+                let w = 2.0 * std::f64::consts::PI * frequency;
+                w * (w * t + phase).cos() * *amplitude
+            }
+            Motion::Ramp {
+                velocity,
+                start,
+                end,
+            } => {
+                if t >= *start && t < *end {
+                    *velocity
+                } else {
+                    V::default()
+                }
+            }
+            Motion::Keyframes(k) => match k.iter().position(|f| f.0 > t) {
+                None | Some(0) => V::default(),
+                Some(i) => (k[i].1 - k[i - 1].1) / (k[i].0 - k[i - 1].0),
+            },
+        }
+    }
+
+    /// Bound on the norm of the acceleration, away from the jumps of the velocity
+    pub fn max_acceleration(&self) -> f64 {
+        match self {
+            Motion::Sine {
+                amplitude,
+                frequency,
+                ..
+            } => {
+                let w = 2.0 * std::f64::consts::PI * frequency;
+                w * w * amplitude.norm()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Next time after `t` at which the velocity can jump
+    pub fn next_jump(&self, t: f64) -> f64 {
+        match self {
+            Motion::Sine { .. } => f64::INFINITY,
+            Motion::Ramp { start, end, .. } => {
+                if t < *start {
+                    *start
+                } else if t < *end {
+                    *end
+                } else {
+                    f64::INFINITY
+                }
+            }
+            Motion::Keyframes(k) => k.iter().map(|f| f.0).find(|&s| s > t).unwrap_or(f64::INFINITY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyframes() {
+        let m = Motion::Keyframes(vec![
+            (1.0, V::new(0.0, 0.0, 0.0)),
+            (2.0, V::new(2.0, 0.0, 0.0)),
+            (4.0, V::new(2.0, 1.0, 0.0)),
+        ]);
+        assert!(m.is_valid());
+        assert_eq!(m.offset(0.0), V::new(0.0, 0.0, 0.0));
+        assert_eq!(m.offset(1.5), V::new(1.0, 0.0, 0.0));
+        assert_eq!(m.offset(3.0), V::new(2.0, 0.5, 0.0));
+        assert_eq!(m.offset(5.0), V::new(2.0, 1.0, 0.0));
+        assert_eq!(m.velocity(1.5), V::new(2.0, 0.0, 0.0));
+        assert_eq!(m.velocity(5.0), V::new(0.0, 0.0, 0.0));
+        assert_eq!(m.next_jump(1.5), 2.0);
+        assert_eq!(m.next_jump(4.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn sine_and_ramp() {
+        let m = Motion::Sine {
+            amplitude: V::new(0.0, 0.0, 2.0),
+            frequency: 0.25,
+            phase: 0.0,
+        };
+        assert!((m.offset(1.0) - V::new(0.0, 0.0, 2.0)).norm() < 1e-12);
+        assert!((m.velocity(0.0) - V::new(0.0, 0.0, std::f64::consts::PI)).norm() < 1e-12);
+        let m = Motion::Ramp {
+            velocity: V::new(1.0, 0.0, 0.0),
+            start: 1.0,
+            end: 3.0,
+        };
+        assert_eq!(m.offset(2.0), V::new(1.0, 0.0, 0.0));
+        assert_eq!(m.offset(4.0), V::new(2.0, 0.0, 0.0));
+        assert_eq!(m.next_jump(0.0), 1.0);
+    }
+
+    #[test]
+    fn invalid() {
+        let keyframes =
+            |t: [f64; 2]| Motion::Keyframes(vec![(t[0], V::default()), (t[1], V::default())]);
+        assert!(!keyframes([1.0, 1.0]).is_valid());
+        assert!(!keyframes([2.0, 1.0]).is_valid());
+        assert!(!keyframes([0.0, f64::NAN]).is_valid());
+        assert!(!Motion::Keyframes(Vec::new()).is_valid());
+        let ramp = Motion::Ramp {
+            velocity: V::new(1.0, 0.0, 0.0),
+            start: 3.0,
+            end: 1.0,
+        };
+        assert!(!ramp.is_valid());
+        let sine = Motion::Sine {
+            amplitude: V::new(f64::INFINITY, 0.0, 0.0),
+            frequency: 1.0,
+            phase: 0.0,
+        };
+        assert!(!sine.is_valid());
+    }
+}
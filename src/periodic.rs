@@ -0,0 +1,105 @@
+use crate::vec3::V;
+
+/// Box of size `l` centered at the origin, periodic along the axes where `p` is true.
+/// With a `shear` rate, the images above and below the box slide along x (Lees-Edwards boundaries),
+/// the box has to be periodic along x and y.
+#[derive(Debug, Clone, Copy)]
+pub struct Periodic {
+    pub l: V,
+    pub p: [bool; 3],
+    pub shear: f64,
+}
+
+impl Periodic {
+    /// Minimum image of the displacement `x`
+    pub fn image(&self, mut x: V) -> V {
+        for d in 0..3 {
+            if self.p[d] {
+                x[d] -= self.l[d] * (x[d] / self.l[d]).round();
+            }
+        }
+        x
+    }
+
+    /// Shift along x of the images above the box at time `t`
+    pub fn offset(&self, t: f64) -> f64 {
+        let d = self.shear * self.l.1 * t;
+        d - self.l.0 * (d / self.l.0).round()
+    }
+
+    /// Velocity along x of the images above the box relative to the box
+    pub fn slide(&self) -> f64 {
+        self.shear * self.l.1
+    }
+
+    /// Minimum image of the displacement `x` at time `t`, and the number of boxes crossed along y
+    pub fn image_at(&self, mut x: V, t: f64) -> (V, f64) {
+        let mut ny = 0.0;
+        if self.shear != 0.0 {
+            ny = (x.1 / self.l.1).round();
+            x.0 -= ny * self.offset(t);
+            x.1 -= ny * self.l.1;
+        }
+        (self.image(x), ny)
+    }
+
+    /// Position `x` and velocity `v` at time `t` brought back inside the box
+    pub fn wrap_at(&self, mut x: V, mut v: V, t: f64) -> (V, V) {
+        if self.shear != 0.0 {
+            let ny = (x.1 / self.l.1 + 0.5).floor();
+            x.0 -= ny * self.offset(t);
+            v.0 -= ny * self.slide();
+        }
+        (self.wrap(x), v)
+    }
+
+    /// Position `x` brought back inside the box
+    pub fn wrap(&self, mut x: V) -> V {
+        for d in 0..3 {
+            if self.p[d] {
+                x[d] -= self.l[d] * (x[d] / self.l[d] + 0.5).floor();
+            }
+        }
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: V, b: V) -> bool {
+        (a - b).norm() < 1e-12
+    }
+
+    #[test]
+    fn minimum_image() {
+        let p = Periodic {
+            l: V::new(1.0, 2.0, 3.0),
+            p: [true, true, false],
+            shear: 0.0,
+        };
+        assert!(close(
+            p.image(V::new(0.9, -1.5, 2.0)),
+            V::new(-0.1, 0.5, 2.0)
+        ));
+        assert!(close(p.wrap(V::new(0.7, 2.5, 5.0)), V::new(-0.3, 0.5, 5.0)));
+    }
+
+    #[test]
+    fn sliding_images() {
+        let p = Periodic {
+            l: V::new(1.0, 1.0, 1.0),
+            p: [true, true, true],
+            shear: 0.5,
+        };
+        // the images above are 0.25 further along x at t = 0.5
+        assert!((p.offset(0.5) - 0.25).abs() < 1e-12);
+        let (x, ny) = p.image_at(V::new(0.3, 0.9, 0.0), 0.5);
+        assert_eq!(ny, 1.0);
+        assert!(close(x, V::new(0.05, -0.1, 0.0)));
+        let (x, v) = p.wrap_at(V::new(0.0, 0.6, 0.0), V::new(0.0, 0.0, 0.0), 0.5);
+        assert!(close(x, V::new(-0.25, -0.4, 0.0)));
+        assert!(close(v, V::new(-0.5, 0.0, 0.0)));
+    }
+}
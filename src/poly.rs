@@ -0,0 +1,160 @@
+// polynomials are stored as coefficients `c[0] + c[1] t + c[2] t^2 + ...`
+
+pub fn eval(c: &[f64], t: f64) -> f64 {
+    c.iter().rev().fold(0.0, |acc, &x| acc * t + x)
+}
+
+fn eval_derivative(c: &[f64], t: f64) -> f64 {
+    c.iter()
+        .enumerate()
+        .skip(1)
+        .rev()
+        .fold(0.0, |acc, (i, &x)| acc * t + i as f64 * x)
+}
+
+fn derivative(c: &[f64]) -> Vec<f64> {
+    c.iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &x)| i as f64 * x)
+        .collect()
+}
+
+fn trim(c: &[f64]) -> &[f64] {
+    let mut n = c.len();
+    while n > 0 && c[n - 1] == 0.0 {
+        n -= 1;
+    }
+    &c[..n]
+}
+
+fn bisect(c: &[f64], mut a: f64, mut b: f64) -> f64 {
+    let mut fa = eval(c, a);
+    for _ in 0..100 {
+        let m = 0.5 * (a + b);
+        if m <= a || m >= b {
+            break;
+        }
+        let fm = eval(c, m);
+        if (fm < 0.0) == (fa < 0.0) {
+            a = m;
+            fa = fm;
+        } else {
+            b = m;
+        }
+    }
+    0.5 * (a + b)
+}
+
+/// Real roots in the interval `lo`..`hi`, sorted
+pub fn roots(c: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let c = trim(c);
+    let mut out = Vec::new();
+    match c.len() {
+        0 | 1 => (),
+        2 => out.push(-c[0] / c[1]),
+        3 => {
+            let dis = c[1] * c[1] - 4.0 * c[2] * c[0];
+            if dis >= 0.0 {
+                // numerically stable form of the quadratic formula
+                let q = -0.5 * (c[1] + c[1].signum() * dis.sqrt());
+                if q != 0.0 {
+                    out.push(c[0] / q);
+                }
+                out.push(q / c[2]);
+            }
+        }
+        n => {
+            // the roots are separated by the roots of the derivative
+            let bound = 1.0 + c[..n - 1].iter().fold(0.0, |m: f64, x| m.max((x / c[n - 1]).abs()));
+            let (lo, hi) = (lo.max(-bound), hi.min(bound));
+            let mut points = vec![lo];
+            points.extend(roots(&derivative(c), lo, hi));
+            points.push(hi);
+            for w in points.windows(2) {
+                let (fa, fb) = (eval(c, w[0]), eval(c, w[1]));
+                if fa == 0.0 {
+                    out.push(w[0]);
+                } else if (fa < 0.0) != (fb < 0.0) && fb != 0.0 {
+                    out.push(bisect(c, w[0], w[1]));
+                }
+            }
+            if eval(c, hi) == 0.0 {
+                out.push(hi);
+            }
+        }
+    }
+    out.retain(|&t| t >= lo && t <= hi);
+    out.sort_by(|a, b| a.total_cmp(b));
+    out.dedup();
+    out
+}
+
+/// Times `t >= 0` at which the polynomial goes from positive to negative, sorted
+pub fn descents(c: &[f64]) -> Vec<f64> {
+    let c = trim(c);
+    let mut out = Vec::new();
+    if c.is_empty() {
+        return out;
+    }
+    if c[0] <= 0.0 {
+        // already below, it counts if it is going further down
+        if let Some(&x) = c[1..].iter().find(|&&x| x != 0.0) {
+            if x < 0.0 {
+                out.push(0.0);
+            }
+        }
+    }
+    for t in roots(c, 0.0, f64::INFINITY) {
+        if eval_derivative(c, t) < 0.0 && out.last() != Some(&t) {
+            out.push(t);
+        }
+    }
+    out
+}
+
+/// First time `t >= 0` at which the polynomial goes from positive to negative
+pub fn first_descent(c: &[f64]) -> f64 {
+    descents(c).first().copied().unwrap_or(f64::INFINITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &[f64], b: &[f64]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9)
+    }
+
+    #[test]
+    fn roots_of_products() {
+        // (t - 1) (t - 2) (t - 3)
+        assert!(close(
+            &roots(&[-6.0, 11.0, -6.0, 1.0], 0.0, 10.0),
+            &[1.0, 2.0, 3.0]
+        ));
+        assert!(close(
+            &roots(&[-6.0, 11.0, -6.0, 1.0], 1.5, 10.0),
+            &[2.0, 3.0]
+        ));
+        // (t + 1) (t - 2) and t^2 + 1
+        assert!(close(&roots(&[-2.0, -1.0, 1.0], -10.0, 10.0), &[-1.0, 2.0]));
+        assert!(roots(&[1.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+        // (t - 1) (t - 2) (t - 3) (t - 4)
+        let c = [24.0, -50.0, 35.0, -10.0, 1.0];
+        assert!(close(&roots(&c, 0.0, 10.0), &[1.0, 2.0, 3.0, 4.0]));
+    }
+
+    #[test]
+    fn descents_from_positive_to_negative() {
+        // (t - 1) (t - 2) goes down at 1 and up at 2
+        assert!(close(&descents(&[2.0, -3.0, 1.0]), &[1.0]));
+        // already below and going down
+        assert!(close(&descents(&[0.0, -1.0]), &[0.0]));
+        assert!(descents(&[0.0, 1.0]).is_empty());
+        // touching zero without crossing
+        assert!(descents(&[1.0, -2.0, 1.0]).is_empty());
+        assert_eq!(first_descent(&[1.0, 0.0, 1.0]), f64::INFINITY);
+        assert!((first_descent(&[1.0, 0.0, -1.0]) - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,1592 @@
+use crate::cells::{Cell, Cells};
+use crate::dynamics::{
+    ball_ball_collision, ball_ball_distance_time, ball_ball_normal, ball_ball_step,
+    ball_container_collision, ball_container_collision_time, ball_container_normal,
+    ball_driven_wall_collision_time, ball_thermal_wall_collision, ball_wall_collision,
+    ball_wall_normal, container_clearance, wall_closest_point, Ball, Container, Interaction,
+    Potential, Wall,
+};
+use crate::collisions::Collisions;
+use crate::diagnostics::{add_kinetic, add_outer, totals, Tensor, Totals};
+use crate::event::{Event, EventKind, EventQueue, Record, Step};
+use crate::motion::Motion;
+use crate::periodic::Periodic;
+use crate::rng::Rng;
+use crate::thermostat::{maxwell, temperature, Thermostat};
+use crate::validation::{
+    ball_problems, bond_problems, container_problems, wall_problems, Problem,
+};
+use crate::vec3::{cross, dot, V};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct Simulation {
+    balls: Vec<Ball>,
+    walls: Vec<Wall>,
+    containers: Vec<Container>,
+    periodic: Option<Periodic>,
+    // global acceleration
+    g: V,
+    // global coefficient of restitution, elastic if `None`
+    e: Option<f64>,
+    // collisions slower than this are elastic, it prevents the inelastic collapse
+    elastic_speed: f64,
+    // tangential coefficient of restitution, smooth balls if `None`
+    beta: Option<f64>,
+    potential: Potential,
+    // interactions between pairs of species, the default one if absent
+    interactions: HashMap<(usize, usize), Interaction>,
+    rng: Rng,
+    thermostat: Option<Thermostat>,
+    // energy given to the balls by each thermal wall
+    wall_heat: Vec<f64>,
+    // minimum and maximum length of the bonds between pairs of balls
+    bonds: HashMap<(usize, usize), (f64, f64)>,
+    // momentum given to each wall, integral of `m v v` over the time and collisional virial
+    // since `window_start`, for the pressure
+    wall_impulse: Vec<V>,
+    kinetic: Tensor,
+    virial: Tensor,
+    window_start: f64,
+    // partners of each ball through the bonds
+    bonded: Vec<Vec<usize>>,
+    // step of the potential in which the pairs are, pairs beyond the range are omitted
+    levels: HashMap<(usize, usize), usize>,
+    t: f64,
+    // time at which the position of each ball (wall) is known
+    ball_t: Vec<f64>,
+    wall_t: Vec<f64>,
+    // position of the walls with a prescribed motion, without the offset, and their mass
+    // when they move freely
+    wall_origin: Vec<V>,
+    wall_mass: Vec<f64>,
+    collisions: Collisions,
+    // collisions processed since the log was last taken, if they are logged
+    log: Option<Vec<Record>>,
+    // last collision processed
+    last: Option<Record>,
+    // displacement taken away from each ball by the periodic boundaries, and its unwrapped
+    // position at the reference time
+    ball_shift: Vec<V>,
+    ball_origin: Vec<V>,
+    // collision counters, used to discard the events predicted before a collision
+    ball_n: Vec<u64>,
+    wall_n: Vec<u64>,
+    cells: Cells,
+    ball_cell: Vec<Cell>,
+    // walls that can move, the others are registered in the cells they reach
+    free_walls: Vec<usize>,
+    // images to consider on top of the minimum image, for the periodic axes with few cells
+    shifts: Vec<V>,
+    queue: EventQueue,
+    ready: bool,
+}
+
+impl Simulation {
+    pub fn add_ball(&mut self, a: Ball) {
+        self.collisions.add_ball();
+        self.ball_shift.push(V::default());
+        self.ball_origin.push(a.x);
+        self.balls.push(a);
+        self.ball_t.push(self.t);
+        self.ball_n.push(0);
+        self.ready = false;
+    }
+
+    pub fn add_wall(&mut self, mut w: Wall) {
+        let mut origin = w.x;
+        self.wall_mass.push(w.m);
+        if let Some(m) = &w.motion {
+            origin -= m.offset(self.t);
+            w.m = f64::INFINITY;
+        }
+        self.walls.push(w);
+        self.wall_origin.push(origin);
+        self.wall_t.push(self.t);
+        self.wall_n.push(0);
+        self.wall_heat.push(0.0);
+        self.wall_impulse.push(V::default());
+        self.ready = false;
+    }
+
+    /// Keep the distance between the balls `i` and `k` between `min` and `max`
+    pub fn add_bond(&mut self, i: usize, k: usize, min: f64, max: f64) {
+        self.bonds.insert((i.min(k), i.max(k)), (min, max));
+        self.ready = false;
+    }
+
+    pub fn bonds(&self) -> Vec<(usize, usize)> {
+        self.bonds.keys().copied().collect()
+    }
+
+    pub fn add_container(&mut self, c: Container) {
+        self.containers.push(c);
+        self.ready = false;
+    }
+
+    /// Prescribed motion of the wall `j`, starting from its current position.
+    /// Without one, the wall moves freely with its mass and current velocity.
+    pub fn set_wall_motion(&mut self, j: usize, motion: Option<Motion>) {
+        self.sync_wall(j);
+        let w = &mut self.walls[j];
+        if w.motion.is_none() {
+            self.wall_mass[j] = w.m;
+        }
+        self.wall_origin[j] = w.x;
+        if let Some(m) = &motion {
+            self.wall_origin[j] -= m.offset(self.t);
+            w.m = f64::INFINITY;
+        } else {
+            w.m = self.wall_mass[j];
+        }
+        w.motion = motion;
+        self.ready = false;
+    }
+
+    pub fn set_periodic(&mut self, periodic: Option<Periodic>) {
+        self.periodic = periodic;
+        self.ready = false;
+    }
+
+    pub fn set_gravity(&mut self, g: V) {
+        self.g = g;
+        self.ready = false;
+    }
+
+    pub fn set_restitution(&mut self, e: Option<f64>, elastic_speed: f64) {
+        self.e = e;
+        self.elastic_speed = elastic_speed;
+    }
+
+    pub fn set_roughness(&mut self, beta: Option<f64>) {
+        self.beta = beta;
+    }
+
+    pub fn set_potential(&mut self, potential: Potential) {
+        self.potential = potential;
+        self.ready = false;
+    }
+
+    pub fn set_interaction(&mut self, s1: usize, s2: usize, interaction: Interaction) {
+        self.interactions.insert((s1.min(s2), s1.max(s2)), interaction);
+        self.ready = false;
+    }
+
+    fn interaction(&self, a: &Ball, b: &Ball) -> Interaction {
+        let key = (a.s.min(b.s), a.s.max(b.s));
+        self.interactions.get(&key).copied().unwrap_or_default()
+    }
+
+    // contact distance of two balls, `None` if they do not interact
+    fn contact(&self, a: &Ball, b: &Ball) -> Option<f64> {
+        let it = self.interaction(a, b);
+        if it.interact {
+            Some(it.sigma.unwrap_or(a.r + b.r))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn set_thermostat(&mut self, thermostat: Option<Thermostat>) {
+        self.thermostat = thermostat;
+        self.ready = false;
+    }
+
+    pub fn wall_heat(&self) -> &[f64] {
+        &self.wall_heat
+    }
+
+    pub fn time(&self) -> f64 {
+        self.t
+    }
+
+    pub fn ball_count(&self) -> usize {
+        self.balls.len()
+    }
+
+    pub fn wall_count(&self) -> usize {
+        self.walls.len()
+    }
+
+    pub fn container_count(&self) -> usize {
+        self.containers.len()
+    }
+
+    /// Whether the state changed since the last prediction of the events
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Problems of the current state, sorted. The overlaps are only looked for
+    /// once the balls, walls, containers and bonds are sound on their own.
+    pub fn problems(&mut self) -> Vec<Problem> {
+        let mut out = Vec::new();
+        for (i, a) in self.balls.iter().enumerate() {
+            out.extend(ball_problems(i, a));
+        }
+        for (j, w) in self.walls.iter().enumerate() {
+            out.extend(wall_problems(j, w));
+        }
+        for (c, container) in self.containers.iter().enumerate() {
+            out.extend(container_problems(c, container));
+        }
+        for (&(i, k), &(min, max)) in &self.bonds {
+            out.extend(bond_problems(i, k, min, max, self.balls.len()));
+        }
+        if !out.is_empty() {
+            out.sort();
+            return out;
+        }
+        if !self.ready {
+            self.init();
+        }
+        self.snapshot();
+
+        // touching is fine, up to rounding
+        let tolerance = 1.0 - 1e-9;
+        for i in 0..self.balls.len() {
+            for k in self.cells.around(self.ball_cell[i]) {
+                if k > i {
+                    let b = self.partner(i, k);
+                    let a = &self.balls[i];
+                    if let Some(sigma) = self.contact(a, &b) {
+                        if (b.x - a.x).norm() < tolerance * sigma {
+                            out.push(Problem::BallsOverlap(i, k));
+                        }
+                    }
+                }
+            }
+            let a = &self.balls[i];
+            for (j, w) in self.walls.iter().enumerate() {
+                if (wall_closest_point(w, a.x) - a.x).norm() < tolerance * a.r {
+                    out.push(Problem::BallWallOverlap(i, j));
+                }
+            }
+            for (c, container) in self.containers.iter().enumerate() {
+                if container_clearance(a.x, container) < tolerance * a.r {
+                    out.push(Problem::BallOutsideContainer(i, c));
+                }
+            }
+        }
+        for (&(i, k), &(min, max)) in &self.bonds {
+            let l = (self.partner(i, k).x - self.balls[i].x).norm();
+            if l < tolerance * min || tolerance * l > max {
+                out.push(Problem::BondStretched(i, k));
+            }
+        }
+        out.sort();
+        out
+    }
+
+    fn sync_ball(&mut self, i: usize) {
+        let a = &mut self.balls[i];
+        let dt = self.t - self.ball_t[i];
+        let acc = self.g + a.a;
+        if dt > 0.0 {
+            // relative to the flow in a sheared box, exact if the acceleration is along x and z
+            let shear = self.periodic.map_or(0.0, |p| p.shear);
+            let u = a.v - V::new(shear * a.x.1, 0.0, 0.0);
+            let b = acc - V::new(shear * a.v.1, 0.0, 0.0);
+            add_kinetic(&mut self.kinetic, a.m, u, b, dt);
+        }
+        a.x += dt * a.v + 0.5 * dt * dt * acc;
+        a.v += dt * acc;
+        self.ball_t[i] = self.t;
+    }
+
+    fn sync_wall(&mut self, j: usize) {
+        let w = &mut self.walls[j];
+        if let Some(m) = &w.motion {
+            w.x = self.wall_origin[j] + m.offset(self.t);
+            w.v = m.velocity(self.t);
+        } else {
+            w.x += (self.t - self.wall_t[j]) * w.v;
+        }
+        self.wall_t[j] = self.t;
+    }
+
+    /// Energy, momenta and centre of mass at the current time. In a periodic box, the
+    /// potential energy of the accelerations jumps when the balls go through the boundaries.
+    pub fn totals(&mut self) -> Totals {
+        if !self.ready {
+            self.init();
+        }
+        self.snapshot();
+        let steps: f64 = self.levels.values().map(|&l| self.potential.energy(l)).sum();
+        totals(&self.balls, &self.walls, self.g, steps)
+    }
+
+    /// Translational temperature at the current time
+    pub fn temperature(&mut self) -> f64 {
+        self.snapshot();
+        temperature(&self.balls, self.periodic.map_or(0.0, |p| p.shear))
+    }
+
+    /// Masses and velocities of the balls relative to the flow of a sheared box, at the current time
+    pub fn peculiar_velocities(&mut self) -> Vec<(f64, V)> {
+        self.snapshot();
+        self.balls
+            .iter()
+            .map(|a| (a.m, a.v - self.streaming(a.x)))
+            .collect()
+    }
+
+    /// Positions of the balls at the current time, without the periodic wrapping
+    pub fn unwrapped_positions(&mut self) -> Vec<V> {
+        self.snapshot();
+        self.balls
+            .iter()
+            .zip(&self.ball_shift)
+            .map(|(a, &s)| a.x + s)
+            .collect()
+    }
+
+    /// Displacement of each ball since the reference time, the time it was added by default
+    pub fn displacements(&mut self) -> Vec<V> {
+        let x = self.unwrapped_positions();
+        x.iter().zip(&self.ball_origin).map(|(&x, &o)| x - o).collect()
+    }
+
+    /// Measure the displacements from now on
+    pub fn reset_displacements(&mut self) {
+        self.ball_origin = self.unwrapped_positions();
+    }
+
+    /// Collisions since they were reset, or since the start
+    pub fn collisions(&self) -> &Collisions {
+        &self.collisions
+    }
+
+    pub fn reset_collisions(&mut self) {
+        self.collisions.reset(self.t);
+    }
+
+    // the ball `i` is synchronized
+    fn record_collision(&mut self, i: usize, wall: bool) {
+        let x = self.balls[i].x + self.ball_shift[i];
+        self.collisions.collide(i, wall, self.t, x);
+    }
+
+    /// Keep a log of the collisions, or stop
+    pub fn set_log(&mut self, on: bool) {
+        self.log = if on { Some(Vec::new()) } else { None };
+    }
+
+    /// Collisions logged since the last call
+    pub fn take_log(&mut self) -> Vec<Record> {
+        self.log.as_mut().map_or(Vec::new(), std::mem::take)
+    }
+
+    // collision of the ball `i` about to get the velocity `va`
+    fn report(&mut self, kind: EventKind, i: usize, normal: V, va: V) {
+        let a = &self.balls[i];
+        let r = Record {
+            t: self.t,
+            kind,
+            normal,
+            impulse: a.m * (va - a.v),
+        };
+        if let Some(log) = &mut self.log {
+            log.push(r);
+        }
+        self.last = Some(r);
+    }
+
+    /// Start measuring the pressure from now on
+    pub fn reset_pressure(&mut self) {
+        self.snapshot();
+        self.wall_impulse.iter_mut().for_each(|p| *p = V::default());
+        self.kinetic = Tensor::default();
+        self.virial = Tensor::default();
+        self.window_start = self.t;
+    }
+
+    /// Momentum given to each wall since the pressure was reset
+    pub fn wall_impulses(&self) -> &[V] {
+        &self.wall_impulse
+    }
+
+    /// Time since the pressure was reset
+    pub fn pressure_window(&self) -> f64 {
+        self.t - self.window_start
+    }
+
+    /// Net normal force per unit area on each wall since the pressure was reset
+    pub fn wall_pressure(&self) -> Vec<f64> {
+        let tau = self.pressure_window();
+        self.walls
+            .iter()
+            .zip(&self.wall_impulse)
+            .map(|(w, &p)| {
+                let n = cross(w.j, w.k);
+                dot(p, n / n.norm()).abs() / (w.area() * tau)
+            })
+            .collect()
+    }
+
+    pub fn periodic(&self) -> Option<Periodic> {
+        self.periodic
+    }
+
+    pub fn containers(&self) -> &[Container] {
+        &self.containers
+    }
+
+    /// Volume of the box if it is periodic along all the axes
+    pub fn box_volume(&self) -> Option<f64> {
+        match self.periodic {
+            Some(p) if p.p.iter().all(|&q| q) => Some(p.l.0 * p.l.1 * p.l.2),
+            _ => None,
+        }
+    }
+
+    /// Pressure tensor of the balls in the `volume` averaged since the pressure was reset,
+    /// kinetic part and collisional virial of the pairs. The walls and containers are left out.
+    pub fn pressure_tensor(&mut self, volume: f64) -> Tensor {
+        self.snapshot();
+        let tau = self.pressure_window();
+        let mut p = Tensor::default();
+        for (r, row) in p.iter_mut().enumerate() {
+            for (q, c) in row.iter_mut().enumerate() {
+                *c = (self.kinetic[r][q] + self.virial[r][q]) / (tau * volume);
+            }
+        }
+        p
+    }
+
+    // collision of the ball `i` with the image `b` of its partner, `va` is the new velocity of `i`
+    fn add_virial(&mut self, i: usize, b: &Ball, va: V) {
+        let a = &self.balls[i];
+        add_outer(&mut self.virial, a.x - b.x, a.m * (va - a.v));
+    }
+
+    /// Balls and walls at the current time
+    pub fn snapshot(&mut self) -> (Vec<Ball>, Vec<Wall>) {
+        for i in 0..self.balls.len() {
+            self.sync_ball(i);
+        }
+        for j in 0..self.walls.len() {
+            self.sync_wall(j);
+        }
+        (self.balls.clone(), self.walls.clone())
+    }
+
+    // ball `k` as seen from ball `i`, through the periodic boundaries
+    fn partner(&self, i: usize, k: usize) -> Ball {
+        let mut b = self.balls[k].clone();
+        if let Some(p) = &self.periodic {
+            let (d, ny) = p.image_at(b.x - self.balls[i].x, self.t);
+            b.x = self.balls[i].x + d;
+            b.v.0 -= ny * p.slide();
+        }
+        b
+    }
+
+    fn level(&self, i: usize, k: usize) -> usize {
+        let key = (i.min(k), i.max(k));
+        self.levels
+            .get(&key)
+            .copied()
+            .unwrap_or(self.potential.steps.len())
+    }
+
+    fn set_level(&mut self, i: usize, k: usize, level: usize) {
+        let key = (i.min(k), i.max(k));
+        if level < self.potential.steps.len() {
+            self.levels.insert(key, level);
+        } else {
+            self.levels.remove(&key);
+        }
+    }
+
+    // time before the distance between the balls `i` and `k` reaches `r`
+    fn pair_time(&self, i: usize, k: usize, r: f64, inward: bool) -> f64 {
+        let mut b = self.partner(i, k);
+        if !inward {
+            // only the closest image can be within the distance
+            return ball_ball_distance_time(&self.balls[i], &b, r, false);
+        }
+        let x = b.x;
+        let mut dt = f64::INFINITY;
+        let mut slides = vec![0.0];
+        if let Some(p) = &self.periodic {
+            // through the sheared boundaries the closest image can change on the way
+            if p.shear != 0.0 && (x.1 - self.balls[k].x.1).abs() > 0.5 * p.l.1 {
+                slides = vec![-p.l.0, 0.0, p.l.0];
+            }
+        }
+        for &s in &self.shifts {
+            for &o in &slides {
+                b.x = x + s + V::new(o, 0.0, 0.0);
+                dt = dt.min(ball_ball_distance_time(&self.balls[i], &b, r, true));
+            }
+        }
+        dt
+    }
+
+    fn push_ball_ball(&mut self, i: usize, k: usize) {
+        self.sync_ball(k);
+        let sigma = match self.contact(&self.balls[i], &self.balls[k]) {
+            Some(sigma) => sigma,
+            None => return,
+        };
+        let level = self.level(i, k);
+        let n = (self.ball_n[i], self.ball_n[k]);
+
+        // inner boundary of the step, the hard core for the first one
+        let (r, kind) = if level == 0 {
+            (sigma, EventKind::BallBall(i, k))
+        } else {
+            let r = sigma * self.potential.steps[level - 1].0;
+            (r, EventKind::Step(i, k, -1))
+        };
+        let dt = self.pair_time(i, k, r, true);
+        self.queue.push(Event {
+            t: self.t + dt.max(0.0),
+            kind,
+            n,
+        });
+
+        // outer boundary of the step
+        if level < self.potential.steps.len() {
+            let r = sigma * self.potential.steps[level].0;
+            let dt = self.pair_time(i, k, r, false);
+            self.queue.push(Event {
+                t: self.t + dt.max(0.0),
+                kind: EventKind::Step(i, k, 1),
+                n,
+            });
+        }
+    }
+
+    fn push_bond(&mut self, i: usize, k: usize) {
+        self.sync_ball(k);
+        let (min, max) = self.bonds[&(i.min(k), i.max(k))];
+        let b = self.partner(i, k);
+        let n = (self.ball_n[i], self.ball_n[k]);
+        if min > 0.0 {
+            let dt = ball_ball_distance_time(&self.balls[i], &b, min, true);
+            self.queue.push(Event {
+                t: self.t + dt.max(0.0),
+                kind: EventKind::Bond(i, k, -1),
+                n,
+            });
+        }
+        let dt = ball_ball_distance_time(&self.balls[i], &b, max, false);
+        self.queue.push(Event {
+            t: self.t + dt.max(0.0),
+            kind: EventKind::Bond(i, k, 1),
+            n,
+        });
+    }
+
+    fn push_ball_wall(&mut self, i: usize, j: usize) {
+        self.sync_wall(j);
+        let (dt, hit) = ball_driven_wall_collision_time(&self.balls[i], self.g, &self.walls[j], self.t);
+        let kind = if hit {
+            EventKind::BallWall(i, j)
+        } else {
+            EventKind::Recheck(i, j)
+        };
+        self.queue.push(Event {
+            t: self.t + dt.max(0.0),
+            kind,
+            n: (self.ball_n[i], self.wall_n[j]),
+        });
+    }
+
+    fn push_ball_container(&mut self, i: usize, c: usize) {
+        let dt = ball_container_collision_time(&self.balls[i], self.g, &self.containers[c]);
+        self.queue.push(Event {
+            t: self.t + dt.max(0.0),
+            kind: EventKind::BallContainer(i, c),
+            n: (self.ball_n[i], 0),
+        });
+    }
+
+    fn push_crossing(&mut self, i: usize) {
+        let a = &self.balls[i];
+        let (dt, axis, dir) = self.cells.exit(self.ball_cell[i], a.x, a.v, self.g + a.a);
+        self.queue.push(Event {
+            t: self.t + dt.max(0.0),
+            kind: EventKind::Crossing(i, axis, dir),
+            n: (self.ball_n[i], 0),
+        });
+    }
+
+    // predict all the future events of ball `i`, it must be synchronized
+    fn predict_ball(&mut self, i: usize) {
+        for k in self.cells.around(self.ball_cell[i]) {
+            if k != i {
+                self.push_ball_ball(i, k);
+            }
+        }
+        for k in self.bonded[i].clone() {
+            self.push_bond(i, k);
+        }
+        for j in self.walls_near(i) {
+            self.push_ball_wall(i, j);
+        }
+        for c in 0..self.containers.len() {
+            self.push_ball_container(i, c);
+        }
+        self.push_crossing(i);
+    }
+
+    // walls that the ball `i` can hit before it leaves its cell
+    fn walls_near(&self, i: usize) -> Vec<usize> {
+        let mut out = self.cells.walls(self.ball_cell[i]).to_vec();
+        out.extend_from_slice(&self.free_walls);
+        out
+    }
+
+    // predict the events between wall `j` and all the balls
+    fn predict_wall(&mut self, j: usize) {
+        for i in 0..self.balls.len() {
+            self.sync_ball(i);
+            self.push_ball_wall(i, j);
+        }
+    }
+
+    // grid over the balls, the containers, the walls that can move and the fixed walls around them,
+    // with cells larger than the biggest ball
+    fn build_cells(&mut self) {
+        let range = self.balls.iter().fold(0.0, |r: f64, a| r.max(2.0 * a.r));
+        let range = self
+            .interactions
+            .values()
+            .fold(range, |r, it| r.max(it.sigma.unwrap_or(0.0)));
+        let range = range * self.potential.range();
+
+        let mut lo = V::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut hi = -lo;
+        let extend = |lo: &mut V, hi: &mut V, x: V| {
+            for d in 0..3 {
+                lo[d] = lo[d].min(x[d]);
+                hi[d] = hi[d].max(x[d]);
+            }
+        };
+        for a in &self.balls {
+            extend(&mut lo, &mut hi, a.x);
+        }
+        let fixed = |w: &Wall| w.motion.is_none() && w.m == f64::INFINITY && w.v == V::default();
+        for w in self.walls.iter().filter(|w| !fixed(w)) {
+            let (a, b) = w.bounds();
+            extend(&mut lo, &mut hi, a);
+            extend(&mut lo, &mut hi, b);
+        }
+        for c in &self.containers {
+            let (a, b, r) = match *c {
+                Container::Sphere { center, radius } => (center, center, radius),
+                Container::Cylinder { base, axis, radius } => (base, base + axis, radius),
+            };
+            let r = V::new(r, r, r);
+            for x in [a - r, a + r, b - r, b + r] {
+                extend(&mut lo, &mut hi, x);
+            }
+        }
+        // the fixed walls only as far as the size of the rest on each side,
+        // a distant mesh would make the cells coarse
+        let (core_lo, core_hi) = (lo, hi);
+        for w in self.walls.iter().filter(|w| fixed(w)) {
+            let (mut a, mut b) = w.bounds();
+            for d in 0..3 {
+                let m = (core_hi[d] - core_lo[d]).max(range);
+                a[d] = a[d].max(core_lo[d] - m);
+                b[d] = b[d].min(core_hi[d] + m);
+            }
+            if (0..3).all(|d| a[d] <= b[d]) {
+                extend(&mut lo, &mut hi, a);
+                extend(&mut lo, &mut hi, b);
+            }
+        }
+        let mut periodic = [false; 3];
+        if let Some(p) = &self.periodic {
+            for d in 0..3 {
+                if p.p[d] {
+                    lo[d] = -0.5 * p.l[d];
+                    hi[d] = 0.5 * p.l[d];
+                }
+            }
+            periodic = p.p;
+        }
+        let sheared = self.periodic.is_some_and(|p| p.shear != 0.0);
+        self.cells = Cells::new(lo, hi, periodic, sheared, range, 2 * self.balls.len());
+
+        // with less than 4 cells the neighbors are not necessarily seen through the minimum image
+        self.shifts = vec![V::new(0.0, 0.0, 0.0)];
+        if let Some(p) = &self.periodic {
+            for d in 0..3 {
+                if p.p[d] && self.cells.count(d) < 4 {
+                    let shifts = std::mem::take(&mut self.shifts);
+                    for s in shifts {
+                        for &o in &[-1.0, 0.0, 1.0] {
+                            let mut s = s;
+                            s[d] += o * p.l[d];
+                            self.shifts.push(s);
+                        }
+                    }
+                }
+            }
+        }
+        self.ball_cell.clear();
+        for i in 0..self.balls.len() {
+            let c = self.cells.cell(self.balls[i].x);
+            self.cells.insert(i, c);
+            self.ball_cell.push(c);
+        }
+
+        // the fixed walls are in the cells where a ball touching them can be
+        let r = self.balls.iter().fold(0.0, |r: f64, a| r.max(a.r));
+        let r = V::new(r, r, r);
+        self.free_walls.clear();
+        for (j, w) in self.walls.iter().enumerate() {
+            if !fixed(w) {
+                self.free_walls.push(j);
+                continue;
+            }
+            let (lo, hi) = w.bounds();
+            self.cells.insert_wall(j, lo - r, hi + r);
+        }
+    }
+
+    fn init(&mut self) {
+        self.queue.clear();
+        for i in 0..self.balls.len() {
+            self.sync_ball(i);
+            if let Some(p) = &self.periodic {
+                let a = &mut self.balls[i];
+                let (x, v) = p.wrap_at(a.x, a.v, self.t);
+                self.ball_shift[i] += a.x - x;
+                a.x = x;
+                a.v = v;
+            }
+        }
+        for j in 0..self.walls.len() {
+            self.sync_wall(j);
+        }
+        self.build_cells();
+        self.bonded = vec![Vec::new(); self.balls.len()];
+        for &(i, k) in self.bonds.keys() {
+            self.bonded[i].push(k);
+            self.bonded[k].push(i);
+        }
+        self.levels.clear();
+        for i in 0..self.balls.len() {
+            for k in self.cells.around(self.ball_cell[i]) {
+                if k > i {
+                    let b = self.partner(i, k);
+                    let a = &self.balls[i];
+                    if let Some(sigma) = self.contact(a, &b) {
+                        let level = self.potential.level((b.x - a.x).norm() / sigma);
+                        self.set_level(i, k, level);
+                    }
+                }
+            }
+        }
+        self.predict_all();
+        self.ready = true;
+    }
+
+    // forget all the events and predict them again, the balls must be synchronized
+    fn predict_all(&mut self) {
+        self.queue.clear();
+        for i in 0..self.balls.len() {
+            for k in self.cells.around(self.ball_cell[i]) {
+                if k > i {
+                    self.push_ball_ball(i, k);
+                }
+            }
+            for k in self.bonded[i].clone() {
+                if k > i {
+                    self.push_bond(i, k);
+                }
+            }
+            for j in self.walls_near(i) {
+                self.push_ball_wall(i, j);
+            }
+            for c in 0..self.containers.len() {
+                self.push_ball_container(i, c);
+            }
+            self.push_crossing(i);
+        }
+        self.push_thermostat();
+    }
+
+    fn push_thermostat(&mut self) {
+        if let Some(thermostat) = self.thermostat {
+            if self.balls.is_empty() {
+                return;
+            }
+            if let Some(dt) = thermostat.interval(self.balls.len(), &mut self.rng) {
+                self.queue.push(Event {
+                    t: self.t + dt,
+                    kind: EventKind::Thermostat,
+                    n: (0, 0),
+                });
+            }
+        }
+    }
+
+    // velocity of the flow imposed by the sheared boundaries at `x`
+    fn streaming(&self, x: V) -> V {
+        let shear = self.periodic.map_or(0.0, |p| p.shear);
+        V::new(shear * x.1, 0.0, 0.0)
+    }
+
+    fn apply_thermostat(&mut self, thermostat: Thermostat) {
+        if let Thermostat::Andersen { temperature, .. } = thermostat {
+            let i = (self.rng.next_u64() % self.balls.len() as u64) as usize;
+            self.sync_ball(i);
+            let a = &self.balls[i];
+            let v = self.streaming(a.x) + maxwell(a.m, temperature, &mut self.rng);
+            self.balls[i].v = v;
+            self.ball_n[i] += 1;
+            self.predict_ball(i);
+            self.push_thermostat();
+        } else {
+            for i in 0..self.balls.len() {
+                self.sync_ball(i);
+            }
+            let shear = self.periodic.map_or(0.0, |p| p.shear);
+            let scale = thermostat.scale(temperature(&self.balls, shear));
+            for i in 0..self.balls.len() {
+                let u = self.streaming(self.balls[i].x);
+                let a = &mut self.balls[i];
+                a.v = u + scale * (a.v - u);
+            }
+            self.predict_all();
+        }
+    }
+
+    fn is_valid(&self, e: &Event) -> bool {
+        match e.kind {
+            EventKind::BallBall(i, k) | EventKind::Step(i, k, _) | EventKind::Bond(i, k, _) => {
+                self.ball_n[i] == e.n.0 && self.ball_n[k] == e.n.1
+            }
+            EventKind::BallWall(i, j) | EventKind::Recheck(i, j) => {
+                self.ball_n[i] == e.n.0 && self.wall_n[j] == e.n.1
+            }
+            EventKind::BallContainer(i, _) | EventKind::Crossing(i, _, _) => self.ball_n[i] == e.n.0,
+            EventKind::Thermostat => true,
+        }
+    }
+
+    // coefficient of restitution of a collision with normal relative velocity `vn`
+    fn restitution(&self, e: f64, vn: f64) -> f64 {
+        if vn.abs() < self.elastic_speed {
+            1.0
+        } else {
+            e
+        }
+    }
+
+    // unless set for the pair of species, the ball-ball coefficient is
+    // the geometric mean of the coefficients of the two balls
+    fn ball_ball_restitution(&self, a: &Ball, b: &Ball) -> f64 {
+        let vn = dot(a.v - b.v, ball_ball_normal(a, b));
+        if let Some(e) = self.interaction(a, b).e {
+            return self.restitution(e, vn);
+        }
+        let e = a.e.or(self.e).unwrap_or(1.0) * b.e.or(self.e).unwrap_or(1.0);
+        self.restitution(e.sqrt(), vn)
+    }
+
+    fn ball_wall_restitution(&self, a: &Ball, w: &Wall) -> f64 {
+        let e = w.e.or(a.e).or(self.e).unwrap_or(1.0);
+        self.restitution(e, dot(a.v - w.v, ball_wall_normal(a, w)))
+    }
+
+    // apply the event, returns the work done by the walls
+    fn process(&mut self, e: Event) -> f64 {
+        let mut work = 0.0;
+        let kind = e.kind;
+        self.last = None;
+        match kind {
+            EventKind::BallBall(i, k) => {
+                self.sync_ball(i);
+                self.sync_ball(k);
+                let b = self.partner(i, k);
+                let u = self.balls[k].v - b.v;
+                let e = self.ball_ball_restitution(&self.balls[i], &b);
+                let beta = self.beta.unwrap_or(-1.0);
+                let ((va, wa), (vb, wb)) = ball_ball_collision(&self.balls[i], &b, e, beta);
+                self.report(kind, i, ball_ball_normal(&self.balls[i], &b), va);
+                self.add_virial(i, &b, va);
+                self.balls[i].v = va;
+                self.balls[i].w = wa;
+                self.balls[k].v = vb + u;
+                self.balls[k].w = wb;
+                self.record_collision(i, false);
+                self.record_collision(k, false);
+                self.ball_n[i] += 1;
+                self.ball_n[k] += 1;
+                self.predict_ball(i);
+                self.predict_ball(k);
+            }
+            EventKind::BallWall(i, j) => {
+                self.sync_ball(i);
+                self.sync_wall(j);
+                if let Some(temperature) = self.walls[j].temperature {
+                    let va = ball_thermal_wall_collision(&self.balls[i], &self.walls[j], temperature, &mut self.rng);
+                    self.report(kind, i, ball_wall_normal(&self.balls[i], &self.walls[j]), va);
+                    let a = &self.balls[i];
+                    work = dot(a.m * (va - a.v), self.walls[j].v);
+                    self.wall_heat[j] += 0.5 * a.m * (dot(va, va) - dot(a.v, a.v));
+                    self.wall_impulse[j] -= a.m * (va - a.v);
+                    self.balls[i].v = va;
+                    self.record_collision(i, true);
+                    self.ball_n[i] += 1;
+                    self.predict_ball(i);
+                    return work;
+                }
+                let e = self.ball_wall_restitution(&self.balls[i], &self.walls[j]);
+                let beta = self.beta.unwrap_or(-1.0);
+                let ((va, wa), vw) = ball_wall_collision(&self.balls[i], &self.walls[j], e, beta);
+                self.report(kind, i, ball_wall_normal(&self.balls[i], &self.walls[j]), va);
+                let a = &mut self.balls[i];
+                let w = &mut self.walls[j];
+                work = dot(a.m * (va - a.v), w.v);
+                self.wall_impulse[j] -= a.m * (va - a.v);
+                a.v = va;
+                a.w = wa;
+                let moved = vw != w.v;
+                w.v = vw;
+                self.record_collision(i, true);
+                self.ball_n[i] += 1;
+                self.predict_ball(i);
+                if moved {
+                    self.wall_n[j] += 1;
+                    self.predict_wall(j);
+                }
+            }
+            EventKind::Bond(i, k, _) => {
+                self.sync_ball(i);
+                self.sync_ball(k);
+                // a step that can not be passed
+                let b = self.partner(i, k);
+                let u = self.balls[k].v - b.v;
+                let (va, vb, _) = ball_ball_step(&self.balls[i], &b, f64::INFINITY);
+                self.report(kind, i, ball_ball_normal(&self.balls[i], &b), va);
+                self.add_virial(i, &b, va);
+                self.balls[i].v = va;
+                self.balls[k].v = vb + u;
+                self.ball_n[i] += 1;
+                self.ball_n[k] += 1;
+                self.predict_ball(i);
+                self.predict_ball(k);
+            }
+            EventKind::BallContainer(i, c) => {
+                self.sync_ball(i);
+                let a = &self.balls[i];
+                let vn = dot(a.v, ball_container_normal(a, &self.containers[c]));
+                let e = self.restitution(a.e.or(self.e).unwrap_or(1.0), vn);
+                let beta = self.beta.unwrap_or(-1.0);
+                let (va, wa) = ball_container_collision(a, &self.containers[c], e, beta);
+                self.report(kind, i, ball_container_normal(&self.balls[i], &self.containers[c]), va);
+                self.balls[i].v = va;
+                self.balls[i].w = wa;
+                self.record_collision(i, true);
+                self.ball_n[i] += 1;
+                self.predict_ball(i);
+            }
+            EventKind::Step(i, k, dir) => {
+                self.sync_ball(i);
+                self.sync_ball(k);
+                let level = self.level(i, k);
+                let new_level = (level as isize + dir) as usize;
+                let du = self.potential.energy(new_level) - self.potential.energy(level);
+                let b = self.partner(i, k);
+                let u = self.balls[k].v - b.v;
+                let (va, vb, passed) = ball_ball_step(&self.balls[i], &b, du);
+                if passed {
+                    self.set_level(i, k, new_level);
+                }
+                self.report(kind, i, ball_ball_normal(&self.balls[i], &b), va);
+                self.add_virial(i, &b, va);
+                self.balls[i].v = va;
+                self.balls[k].v = vb + u;
+                self.ball_n[i] += 1;
+                self.ball_n[k] += 1;
+                self.predict_ball(i);
+                self.predict_ball(k);
+            }
+            EventKind::Recheck(i, j) => {
+                self.sync_ball(i);
+                self.push_ball_wall(i, j);
+            }
+            EventKind::Thermostat => {
+                if let Some(thermostat) = self.thermostat {
+                    self.apply_thermostat(thermostat);
+                }
+            }
+            EventKind::Crossing(i, axis, dir) => {
+                self.sync_ball(i);
+                let (mut c, wrapped) = self.cells.step(self.ball_cell[i], axis, dir);
+                if let (true, Some(p)) = (wrapped, self.periodic) {
+                    let dir = dir as f64;
+                    let a = &mut self.balls[i];
+                    let x = a.x;
+                    a.x[axis] -= dir * p.l[axis];
+                    if axis == 1 && p.shear != 0.0 {
+                        // the ball comes back from a sliding image, somewhere else along x and faster
+                        a.x.0 -= dir * p.offset(self.t);
+                        a.x.0 -= p.l.0 * (a.x.0 / p.l.0).round();
+                        a.v.0 -= dir * p.slide();
+                    }
+                    self.ball_shift[i] += x - a.x;
+                    if axis == 1 && p.shear != 0.0 {
+                        c[0] = self.cells.cell(self.balls[i].x)[0];
+                        self.cells.remove(i, self.ball_cell[i]);
+                        self.cells.insert(i, c);
+                        self.ball_cell[i] = c;
+                        self.ball_n[i] += 1;
+                        self.predict_ball(i);
+                        return work;
+                    }
+                }
+                let old = self.ball_cell[i];
+                self.cells.remove(i, old);
+                self.cells.insert(i, c);
+                self.ball_cell[i] = c;
+                for k in self.cells.layer(c, axis, dir) {
+                    self.push_ball_ball(i, k);
+                }
+                for j in self.cells.walls(c).to_vec() {
+                    if !self.cells.walls(old).contains(&j) {
+                        self.push_ball_wall(i, j);
+                    }
+                }
+                self.push_crossing(i);
+            }
+        }
+        work
+    }
+
+    /// Advance the simulation by `dt`, processing all the events on the way.
+    /// Returns the work done by the walls on the balls.
+    pub fn evolve(&mut self, dt: f64) -> f64 {
+        if !self.ready {
+            self.init();
+        }
+        let t_end = self.t + dt;
+        let mut work = 0.0;
+        while self.queue.next_time() <= t_end {
+            let e = self.queue.pop().unwrap();
+            if self.is_valid(&e) {
+                self.t = e.t;
+                work += self.process(e);
+            }
+        }
+        self.t = t_end;
+        self.prune();
+        work
+    }
+
+    /// Process the events up to the next collision and return it, or up to `t_end` if there is
+    /// none before. The search gives up after `max_events` events without a collision.
+    pub fn step(&mut self, t_end: f64, max_events: usize) -> Step {
+        if !self.ready {
+            self.init();
+        }
+        let mut events = 0;
+        while self.queue.next_time() <= t_end && self.queue.next_time().is_finite() {
+            if events == max_events {
+                self.prune();
+                return Step::GaveUp;
+            }
+            let e = self.queue.pop().unwrap();
+            if self.is_valid(&e) {
+                self.t = e.t;
+                self.process(e);
+                events += 1;
+                if let Some(r) = self.last.take() {
+                    self.prune();
+                    return Step::Collision(r);
+                }
+            }
+        }
+        if t_end.is_finite() {
+            self.t = t_end;
+        }
+        self.prune();
+        Step::Quiet
+    }
+
+    // get rid of the stale events from time to time
+    fn prune(&mut self) {
+        if self.queue.len() > 64 * (self.balls.len() + self.walls.len() + self.containers.len() + 1) {
+            let mut queue = std::mem::take(&mut self.queue);
+            queue.retain(|e| self.is_valid(e));
+            self.queue = queue;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::enskog;
+    use crate::diagnostics::Monitor;
+    use crate::dynamics::Shape;
+
+    // balls on a lattice of `n^3` sites spaced by `d`, around the origin, with random velocities
+    fn gas(n: usize, d: f64, r: f64, seed: u64) -> Simulation {
+        let mut sim = Simulation::default();
+        let mut rng = Rng::new(seed);
+        let o = 0.5 * (n - 1) as f64;
+        for x in 0..n {
+            for y in 0..n {
+                for z in 0..n {
+                    sim.add_ball(Ball {
+                        x: d * V::new(x as f64 - o, y as f64 - o, z as f64 - o),
+                        v: V::new(rng.normal(), rng.normal(), rng.normal()),
+                        a: V::default(),
+                        w: V::default(),
+                        m: 1.0 + rng.uniform(),
+                        r: r * (0.8 + 0.2 * rng.uniform()),
+                        i: 0.0,
+                        e: None,
+                        s: 0,
+                    });
+                }
+            }
+        }
+        sim
+    }
+
+    fn wall(x: V, j: V, k: V) -> Wall {
+        Wall {
+            x,
+            v: V::default(),
+            j,
+            k,
+            m: f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
+        }
+    }
+
+    // evolve and check that the energy is conserved and that nothing overlaps
+    fn check(sim: &mut Simulation, steps: usize) {
+        let e0 = sim.totals().energy();
+        for _ in 0..steps {
+            sim.evolve(0.5);
+            assert_eq!(sim.problems(), Vec::new(), "at t = {}", sim.time());
+            let e = sim.totals().energy();
+            assert!(
+                (e - e0).abs() < 1e-10 * e0.abs(),
+                "energy {} instead of {}",
+                e,
+                e0
+            );
+        }
+        assert!(sim.collisions().ball_ball.iter().sum::<u64>() > 1000);
+    }
+
+    #[test]
+    fn gas_in_a_box() {
+        let mut sim = gas(5, 0.5, 0.2, 1);
+        let a = 1.5;
+        let (x, y, z) = (
+            V::new(2.0 * a, 0.0, 0.0),
+            V::new(0.0, 2.0 * a, 0.0),
+            V::new(0.0, 0.0, 2.0 * a),
+        );
+        let lo = V::new(-a, -a, -a);
+        for w in [
+            wall(lo, y, z),
+            wall(lo + x, y, z),
+            wall(lo, x, z),
+            wall(lo + y, x, z),
+            wall(lo, x, y),
+            wall(lo + z, x, y),
+        ] {
+            sim.add_wall(w);
+        }
+        check(&mut sim, 10);
+    }
+
+    // box of side `2a` around the origin with each face cut into `2 n^2` triangles
+    fn triangulated_box(sim: &mut Simulation, a: f64, n: usize) {
+        let h = 2.0 * a / n as f64;
+        let axes = [
+            V::new(h, 0.0, 0.0),
+            V::new(0.0, h, 0.0),
+            V::new(0.0, 0.0, h),
+        ];
+        for d in 0..3 {
+            let (j, k) = (axes[(d + 1) % 3], axes[(d + 2) % 3]);
+            for &side in &[-a, a] {
+                let mut o = V::new(-a, -a, -a);
+                o[d] = side;
+                for u in 0..n {
+                    for v in 0..n {
+                        let x = o + u as f64 * j + v as f64 * k;
+                        for (x, j, k) in [(x, j, k), (x + j + k, -1.0 * j, -1.0 * k)] {
+                            sim.add_wall(Wall {
+                                shape: Shape::Triangle,
+                                ..wall(x, j, k)
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gas_in_a_triangulated_box_with_distant_walls() {
+        let mut sim = gas(5, 0.5, 0.2, 3);
+        triangulated_box(&mut sim, 1.5, 4);
+        let mut far = sim.clone();
+        for k in 0..2000 {
+            let x = V::new(100.0 + 3.0 * (k % 40) as f64, 3.0 * (k / 40) as f64, 50.0);
+            far.add_wall(Wall {
+                shape: Shape::Triangle,
+                ..wall(x, V::new(1.0, 0.0, 0.0), V::new(0.0, 1.0, 0.0))
+            });
+        }
+        check(&mut sim, 10);
+        check(&mut far, 10);
+        // the distant walls are never looked at, so nothing changes
+        let x = |sim: &mut Simulation| sim.snapshot().0.iter().map(|a| a.x).collect::<Vec<_>>();
+        assert_eq!(x(&mut far), x(&mut sim));
+    }
+
+    #[test]
+    fn gas_in_a_sphere_keeps_its_angular_momentum() {
+        let mut sim = gas(5, 0.5, 0.2, 6);
+        sim.add_container(Container::Sphere {
+            center: V::default(),
+            radius: 2.0,
+        });
+        let mut monitor = Monitor::new(sim.totals(), 1e-10);
+        monitor.angular_momentum = true;
+        check(&mut sim, 10);
+        assert_eq!(monitor.record(sim.time(), &sim.totals()), None);
+        // unlike the momentum, pushed around by the sphere
+        let d = monitor.drift(&sim.totals());
+        assert!(d.momentum > 1e-3);
+    }
+
+    #[test]
+    fn pressure_of_an_ideal_gas() {
+        let mut sim = gas(5, 0.5, 0.001, 7);
+        triangulated_box(&mut sim, 1.5, 1);
+        sim.reset_pressure();
+        sim.evolve(100.0);
+        let p = 2.0 * sim.totals().kinetic / (3.0 * 27.0);
+        let walls = sim.wall_pressure();
+        // the two triangles of a face share the hits
+        let faces: Vec<f64> = walls.chunks(2).map(|c| 0.5 * (c[0] + c[1])).collect();
+        let mean = faces.iter().sum::<f64>() / 6.0;
+        assert!((mean / p - 1.0).abs() < 0.05, "{} instead of {}", mean, p);
+        // the balls almost never meet, the pressure is all kinetic, and it differs along the axes
+        // as they keep the speeds along them
+        let t = sim.pressure_tensor(27.0);
+        assert!(((t[0][0] + t[1][1] + t[2][2]) / 3.0 - p).abs() < 1e-9 * p);
+        for d in 0..3 {
+            for f in &faces[2 * d..2 * d + 2] {
+                assert!((f / t[d][d] - 1.0).abs() < 0.05, "{:?} {:?}", faces, t);
+            }
+        }
+    }
+
+    #[test]
+    fn virial_of_hard_spheres() {
+        let mut sim = gas(5, 0.6, 0.25, 8);
+        sim.set_periodic(Some(Periodic {
+            l: V::new(3.0, 3.0, 3.0),
+            p: [true, true, true],
+            shear: 0.0,
+        }));
+        sim.evolve(1.0);
+        sim.reset_pressure();
+        sim.evolve(20.0);
+        let ideal = 2.0 * sim.totals().kinetic / (3.0 * 27.0);
+        let t = sim.pressure_tensor(27.0);
+        for (r, row) in t.iter().enumerate() {
+            assert!(row[r] > 1.2 * ideal, "{:?} {}", t, ideal);
+            for (q, &c) in row.iter().enumerate() {
+                assert!((c - t[q][r]).abs() < 0.1 * ideal);
+            }
+        }
+    }
+
+    #[test]
+    fn collisions_of_a_dilute_gas() {
+        // equal balls of diameter 0.2 at the density 1
+        let mut sim = Simulation::default();
+        let mut rng = Rng::new(9);
+        for k in 0..216 {
+            let x = V::new((k % 6) as f64, (k / 6 % 6) as f64, (k / 36) as f64);
+            sim.add_ball(Ball {
+                x: x - V::new(2.5, 2.5, 2.5),
+                v: V::new(rng.normal(), rng.normal(), rng.normal()),
+                a: V::default(),
+                w: V::default(),
+                m: 1.0,
+                r: 0.1,
+                i: 0.0,
+                e: None,
+                s: 0,
+            });
+        }
+        sim.set_periodic(Some(Periodic {
+            l: V::new(6.0, 6.0, 6.0),
+            p: [true, true, true],
+            shear: 0.0,
+        }));
+        sim.evolve(10.0);
+        sim.reset_collisions();
+        sim.evolve(100.0);
+        let c = sim.collisions();
+        let rate = c.ball_ball.iter().sum::<u64>() as f64 / (216.0 * 100.0);
+        let path = c.flight_distance / c.flights as f64;
+        let (frequency, mean_free_path) = enskog(1.0, 0.2, 1.0, sim.temperature());
+        assert!(
+            (rate / frequency - 1.0).abs() < 0.1,
+            "{} {}",
+            rate,
+            frequency
+        );
+        assert!(
+            (path / mean_free_path - 1.0).abs() < 0.1,
+            "{} {}",
+            path,
+            mean_free_path
+        );
+    }
+
+    #[test]
+    fn square_well_fluid_in_a_periodic_box() {
+        let mut sim = gas(5, 0.6, 0.2, 2);
+        sim.set_periodic(Some(Periodic {
+            l: V::new(3.0, 3.0, 3.0),
+            p: [true, true, true],
+            shear: 0.0,
+        }));
+        sim.set_potential(Potential {
+            steps: vec![(1.5, -0.5)],
+        });
+        let p0 = sim.totals().momentum;
+        check(&mut sim, 10);
+        assert!((sim.totals().momentum - p0).norm() < 1e-10);
+    }
+
+    #[test]
+    fn chains_across_a_periodic_box() {
+        let mut sim = gas(5, 0.6, 0.2, 4);
+        let p = Periodic {
+            l: V::new(3.0, 3.0, 3.0),
+            p: [true, true, true],
+            shear: 0.0,
+        };
+        sim.set_periodic(Some(p));
+        // chains of five balls along z
+        for i in 0..125 {
+            if i % 5 != 4 {
+                sim.add_bond(i, i + 1, 0.5, 0.8);
+            }
+        }
+        check(&mut sim, 10);
+        let balls = sim.snapshot().0;
+        let mut across = 0;
+        for (i, k) in sim.bonds() {
+            let d = balls[k].x - balls[i].x;
+            if d.norm() > 1.5 {
+                across += 1;
+            }
+            let l = p.image(d).norm();
+            assert!(
+                (0.5 - 1e-9..=0.8 + 1e-9).contains(&l),
+                "bond {} {} of length {}",
+                i,
+                k,
+                l
+            );
+        }
+        // some of them go through the sides of the box
+        assert!(across > 0);
+    }
+
+    #[test]
+    fn sheared_gas() {
+        let mut sim = gas(5, 0.6, 0.2, 5);
+        sim.set_periodic(Some(Periodic {
+            l: V::new(3.0, 3.0, 3.0),
+            p: [true, true, true],
+            shear: 0.5,
+        }));
+        let e0 = sim.totals().energy();
+        for _ in 0..20 {
+            sim.evolve(0.5);
+            assert_eq!(sim.problems(), Vec::new(), "at t = {}", sim.time());
+        }
+        // the velocity along x follows the images, and the shear heats the gas
+        let balls = sim.snapshot().0;
+        let n = balls.len() as f64;
+        let y = balls.iter().map(|a| a.x.1).sum::<f64>() / n;
+        let vx = balls.iter().map(|a| a.v.0).sum::<f64>() / n;
+        let cov = balls
+            .iter()
+            .map(|a| (a.x.1 - y) * (a.v.0 - vx))
+            .sum::<f64>();
+        let var = balls.iter().map(|a| (a.x.1 - y).powi(2)).sum::<f64>();
+        assert!((cov / var - 0.5).abs() < 0.2, "slope {}", cov / var);
+        assert!(sim.totals().energy() > e0);
+    }
+
+    // two balls of radius 0.5 and of species 0 and 1 heading at each other, 4 apart
+    fn pair(interaction: Interaction) -> Simulation {
+        let mut sim = Simulation::default();
+        for &(x, s) in &[(-2.0, 0), (2.0, 1)] {
+            sim.add_ball(Ball {
+                x: V::new(x, 0.0, 0.0),
+                v: V::new(-0.5 * x, 0.0, 0.0),
+                a: V::default(),
+                w: V::default(),
+                m: 1.0,
+                r: 0.5,
+                i: 0.0,
+                e: None,
+                s,
+            });
+        }
+        sim.set_interaction(0, 1, interaction);
+        sim
+    }
+
+    #[test]
+    fn interactions_between_species() {
+        let t = |mut sim: Simulation| match sim.step(10.0, 1000) {
+            Step::Collision(r) => Some(r.t),
+            _ => None,
+        };
+        assert_eq!(t(pair(Interaction::default())), Some(1.5));
+        let sigma = Interaction {
+            sigma: Some(2.0),
+            ..Interaction::default()
+        };
+        assert_eq!(t(pair(sigma)), Some(1.0));
+        let ghost = Interaction {
+            interact: false,
+            ..Interaction::default()
+        };
+        assert!(matches!(pair(ghost).step(10.0, 1000), Step::Quiet));
+    }
+
+    #[test]
+    fn steps_without_collisions() {
+        // balls in parallel, crossing the cells of a periodic box forever
+        let mut sim = Simulation::default();
+        for k in 0..8 {
+            sim.add_ball(Ball {
+                x: 5.0 * V::new((k % 2) as f64, (k / 2 % 2) as f64, (k / 4) as f64),
+                v: V::new(1.0, 0.3, 0.1),
+                a: V::default(),
+                w: V::default(),
+                m: 1.0,
+                r: 0.5,
+                i: 0.0,
+                e: None,
+                s: 0,
+            });
+        }
+        sim.set_periodic(Some(Periodic {
+            l: V::new(10.0, 10.0, 10.0),
+            p: [true, true, true],
+            shear: 0.0,
+        }));
+        assert!(matches!(sim.step(f64::INFINITY, 100), Step::GaveUp));
+        let t = sim.time();
+        assert!(t > 0.0 && t.is_finite());
+        assert!(matches!(sim.step(t + 1.0, 100_000), Step::Quiet));
+        assert_eq!(sim.time(), t + 1.0);
+    }
+
+    #[test]
+    fn overlaps() {
+        let with = |xs: &[f64]| {
+            let mut sim = Simulation::default();
+            for &x in xs {
+                sim.add_ball(Ball {
+                    x: V::new(x, 0.0, 0.0),
+                    v: V::default(),
+                    a: V::default(),
+                    w: V::default(),
+                    m: 1.0,
+                    r: 0.5,
+                    i: 0.0,
+                    e: None,
+                    s: 0,
+                });
+            }
+            sim
+        };
+        let mut sim = with(&[-1.4, 0.0, 1.4]);
+        assert_eq!(sim.problems(), Vec::new());
+        // only through the sides of the box
+        sim.set_periodic(Some(Periodic {
+            l: V::new(3.0, 3.0, 3.0),
+            p: [true, true, true],
+            shear: 0.0,
+        }));
+        assert_eq!(sim.problems(), vec![Problem::BallsOverlap(0, 2)]);
+
+        let mut sim = with(&[0.0, 2.0]);
+        sim.add_wall(wall(
+            V::new(1.8, -1.0, -1.0),
+            V::new(0.0, 2.0, 0.0),
+            V::new(0.0, 0.0, 2.0),
+        ));
+        sim.add_container(Container::Sphere {
+            center: V::new(0.5, 0.0, 0.0),
+            radius: 1.2,
+        });
+        sim.add_bond(0, 1, 0.5, 1.5);
+        assert_eq!(
+            sim.problems(),
+            vec![
+                Problem::BallWallOverlap(1, 0),
+                Problem::BallOutsideContainer(1, 0),
+                Problem::BondStretched(0, 1),
+            ]
+        );
+        // the objects themselves come first
+        sim.add_bond(0, 2, 0.5, 1.5);
+        assert_eq!(sim.problems(), vec![Problem::BondBalls(0, 2)]);
+    }
+
+    #[test]
+    fn released_wall() {
+        let mut sim = Simulation::default();
+        let mut w = wall(
+            V::new(-1.0, -1.0, 0.0),
+            V::new(2.0, 0.0, 0.0),
+            V::new(0.0, 2.0, 0.0),
+        );
+        w.m = 3.0;
+        sim.add_wall(w);
+        sim.set_wall_motion(
+            0,
+            Some(Motion::Ramp {
+                velocity: V::new(0.0, 0.0, 1.0),
+                start: 0.0,
+                end: 10.0,
+            }),
+        );
+        sim.evolve(1.0);
+        assert_eq!(sim.snapshot().1[0].m, f64::INFINITY);
+        sim.set_wall_motion(0, None);
+        let w = &sim.snapshot().1[0];
+        assert_eq!((w.m, w.v, w.x.2), (3.0, V::new(0.0, 0.0, 1.0), 1.0));
+    }
+}
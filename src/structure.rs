@@ -0,0 +1,374 @@
+use crate::dynamics::{container_clearance, Container};
+use crate::periodic::Periodic;
+use crate::rng::Rng;
+use crate::vec3::V;
+use std::f64::consts::PI;
+
+/// Space over which the balls are spread, for the normalization of g(r)
+#[derive(Debug, Clone)]
+pub enum Region {
+    // periodic along all the axes
+    Periodic(Periodic),
+    Container(Container),
+    // axis-aligned box between two corners
+    Box(V, V),
+}
+
+impl Region {
+    /// Part of the region where the centers of balls of radius `r` can be, `None` if there is none
+    pub fn shrink(&self, r: f64) -> Option<Region> {
+        match *self {
+            Region::Periodic(p) => Some(Region::Periodic(p)),
+            Region::Container(Container::Sphere { center, radius }) => (radius > r).then_some(
+                Region::Container(Container::Sphere {
+                    center,
+                    radius: radius - r,
+                }),
+            ),
+            Region::Container(Container::Cylinder { base, axis, radius }) => {
+                let u = axis / axis.norm();
+                (radius > r && axis.norm() > 2.0 * r).then_some(Region::Container(Container::Cylinder {
+                    base: base + r * u,
+                    axis: axis - 2.0 * r * u,
+                    radius: radius - r,
+                }))
+            }
+            Region::Box(lo, hi) => {
+                let r = V::new(r, r, r);
+                let (lo, hi) = (lo + r, hi - r);
+                let sound = (0..3).all(|d| lo[d].is_finite() && hi[d].is_finite() && lo[d] < hi[d]);
+                sound.then_some(Region::Box(lo, hi))
+            }
+        }
+    }
+
+    fn contains(&self, x: V) -> bool {
+        match self {
+            Region::Periodic(_) => true,
+            Region::Container(c) => container_clearance(x, c) >= 0.0,
+            Region::Box(lo, hi) => (0..3).all(|d| x[d] >= lo[d] && x[d] <= hi[d]),
+        }
+    }
+
+    fn bounds(&self) -> (V, V) {
+        match *self {
+            Region::Periodic(p) => (-0.5 * p.l, 0.5 * p.l),
+            Region::Container(Container::Sphere { center, radius }) => {
+                let r = V::new(radius, radius, radius);
+                (center - r, center + r)
+            }
+            Region::Container(Container::Cylinder { base, axis, radius }) => {
+                let r = V::new(radius, radius, radius);
+                let (a, b) = (base, base + axis);
+                let lo = V::new(a.0.min(b.0), a.1.min(b.1), a.2.min(b.2));
+                let hi = V::new(a.0.max(b.0), a.1.max(b.1), a.2.max(b.2));
+                (lo - r, hi + r)
+            }
+            Region::Box(lo, hi) => (lo, hi),
+        }
+    }
+
+    // uniform in the region, by rejection from the bounds
+    fn random_point(&self, rng: &mut Rng) -> V {
+        let (lo, hi) = self.bounds();
+        loop {
+            let x = V::new(
+                lo.0 + rng.uniform() * (hi.0 - lo.0),
+                lo.1 + rng.uniform() * (hi.1 - lo.1),
+                lo.2 + rng.uniform() * (hi.2 - lo.2),
+            );
+            if self.contains(x) {
+                return x;
+            }
+        }
+    }
+
+    fn volume(&self) -> f64 {
+        match self {
+            Region::Periodic(p) => p.l.0 * p.l.1 * p.l.2,
+            Region::Container(Container::Sphere { radius, .. }) => 4.0 / 3.0 * PI * radius.powi(3),
+            Region::Container(Container::Cylinder { axis, radius, .. }) => {
+                PI * radius * radius * axis.norm()
+            }
+            Region::Box(lo, hi) => (hi.0 - lo.0) * (hi.1 - lo.1) * (hi.2 - lo.2),
+        }
+    }
+
+    // from `a` to `b`, through the closest image in a periodic box
+    fn displacement(&self, a: V, b: V, t: f64) -> V {
+        match self {
+            Region::Periodic(p) => p.image_at(b - a, t).0,
+            _ => b - a,
+        }
+    }
+}
+
+// random pairs used to find the fraction of the shells that is inside the region
+const OVERLAP_SAMPLES: usize = 100_000;
+
+/// Radial distribution function of the balls, averaged over snapshots
+#[derive(Debug, Clone)]
+pub struct Rdf {
+    pub r_max: f64,
+    pub bins: usize,
+    region: Region,
+    volume: f64,
+    // fraction of the shell of each bin around a point of the region that is in the region
+    overlap: Vec<f64>,
+    counts: Vec<f64>,
+    // sum over the snapshots of the number of pairs
+    pairs: f64,
+}
+
+impl Rdf {
+    pub fn new(region: Region, r_max: f64, bins: usize) -> Rdf {
+        let mut rng = Rng::new(0);
+        let dr = r_max / bins as f64;
+        let overlap = match region {
+            Region::Periodic(_) => vec![1.0; bins],
+            _ => {
+                let mut inside = vec![0.0; bins];
+                for _ in 0..OVERLAP_SAMPLES {
+                    let x = region.random_point(&mut rng);
+                    // isotropic direction
+                    let z = 2.0 * rng.uniform() - 1.0;
+                    let phi = 2.0 * PI * rng.uniform();
+                    let s = (1.0 - z * z).sqrt();
+                    let u = V::new(s * phi.cos(), s * phi.sin(), z);
+                    for (b, c) in inside.iter_mut().enumerate() {
+                        if region.contains(x + (b as f64 + 0.5) * dr * u) {
+                            *c += 1.0;
+                        }
+                    }
+                }
+                inside.iter().map(|c| c / OVERLAP_SAMPLES as f64).collect()
+            }
+        };
+        Rdf {
+            r_max,
+            bins,
+            volume: region.volume(),
+            region,
+            overlap,
+            counts: vec![0.0; bins],
+            pairs: 0.0,
+        }
+    }
+
+    /// Add the snapshot of the positions `x` at time `t`
+    pub fn sample(&mut self, x: &[V], t: f64) {
+        let dr = self.r_max / self.bins as f64;
+        for i in 0..x.len() {
+            for k in i + 1..x.len() {
+                let r = self.region.displacement(x[i], x[k], t).norm();
+                if r < self.r_max {
+                    self.counts[((r / dr) as usize).min(self.bins - 1)] += 1.0;
+                }
+            }
+        }
+        let n = x.len() as f64;
+        self.pairs += 0.5 * n * (n - 1.0);
+    }
+
+    /// Centers of the bins and g(r)
+    pub fn result(&self) -> (Vec<f64>, Vec<f64>) {
+        let dr = self.r_max / self.bins as f64;
+        (0..self.bins)
+            .map(|b| {
+                let (r0, r1) = (b as f64 * dr, (b + 1) as f64 * dr);
+                let shell = 4.0 / 3.0 * PI * (r1.powi(3) - r0.powi(3));
+                // pairs expected at this distance if the balls were independent
+                let ideal = self.pairs * shell * self.overlap[b] / self.volume;
+                let g = if ideal > 0.0 {
+                    self.counts[b] / ideal
+                } else {
+                    0.0
+                };
+                (0.5 * (r0 + r1), g)
+            })
+            .unzip()
+    }
+}
+
+/// Static structure factor on the reciprocal lattice of a periodic box, averaged over snapshots
+/// and over the wavevectors in shells of width `dk`
+#[derive(Debug, Clone)]
+pub struct StructureFactor {
+    pub l: V,
+    pub n_max: usize,
+    pub dk: f64,
+    sums: Vec<f64>,
+    // sum of |k| and number of terms in each shell
+    ks: Vec<f64>,
+    counts: Vec<f64>,
+}
+
+impl StructureFactor {
+    /// Wavevectors `2 pi (n0 / l0, n1 / l1, n2 / l2)` with `|n_d| <= n_max`
+    pub fn new(l: V, n_max: usize) -> StructureFactor {
+        let dk = 2.0 * PI / l.0.max(l.1).max(l.2);
+        let k_max =
+            2.0 * PI * n_max as f64 * (1.0 / l.0).max(1.0 / l.1).max(1.0 / l.2) * 3f64.sqrt();
+        let shells = (k_max / dk) as usize + 1;
+        StructureFactor {
+            l,
+            n_max,
+            dk,
+            sums: vec![0.0; shells],
+            ks: vec![0.0; shells],
+            counts: vec![0.0; shells],
+        }
+    }
+
+    /// Add the snapshot of the positions `x`
+    pub fn sample(&mut self, x: &[V]) {
+        let m = self.n_max as isize;
+        let w = 2 * self.n_max + 1;
+        // exp(i 2 pi n x_d / l_d) for each ball, axis and n from -n_max to n_max
+        let phases: Vec<[Vec<(f64, f64)>; 3]> = x
+            .iter()
+            .map(|x| {
+                let axis = |d: usize| {
+                    (-m..=m)
+                        .map(|n| {
+                            let a = 2.0 * PI * n as f64 * x[d] / self.l[d];
+                            (a.cos(), a.sin())
+                        })
+                        .collect()
+                };
+                [axis(0), axis(1), axis(2)]
+            })
+            .collect();
+        let mul = |a: (f64, f64), b: (f64, f64)| (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0);
+        for n0 in 0..w {
+            for n1 in 0..w {
+                for n2 in 0..w {
+                    let n = [n0 as isize - m, n1 as isize - m, n2 as isize - m];
+                    if n == [0, 0, 0] {
+                        continue;
+                    }
+                    let k = 2.0
+                        * PI
+                        * V::new(
+                            n[0] as f64 / self.l.0,
+                            n[1] as f64 / self.l.1,
+                            n[2] as f64 / self.l.2,
+                        );
+                    let rho = phases.iter().fold((0.0, 0.0), |s, p| {
+                        let e = mul(mul(p[0][n0], p[1][n1]), p[2][n2]);
+                        (s.0 + e.0, s.1 + e.1)
+                    });
+                    let b = (k.norm() / self.dk) as usize;
+                    self.sums[b] += (rho.0 * rho.0 + rho.1 * rho.1) / x.len() as f64;
+                    self.ks[b] += k.norm();
+                    self.counts[b] += 1.0;
+                }
+            }
+        }
+    }
+
+    /// Mean |k| and S(k) of the shells with wavevectors
+    pub fn result(&self) -> (Vec<f64>, Vec<f64>) {
+        (0..self.sums.len())
+            .filter(|&b| self.counts[b] > 0.0)
+            .map(|b| (self.ks[b] / self.counts[b], self.sums[b] / self.counts[b]))
+            .unzip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn periodic(l: f64) -> Periodic {
+        Periodic {
+            l: V::new(l, l, l),
+            p: [true, true, true],
+            shear: 0.0,
+        }
+    }
+
+    // g(r) of uniform random points in the region, which should be 1
+    fn uniform_rdf(region: Region, n: usize, r_max: f64) -> Vec<f64> {
+        let mut rdf = Rdf::new(region.clone(), r_max, 10);
+        let mut rng = Rng::new(1);
+        for _ in 0..20 {
+            let x: Vec<V> = (0..n).map(|_| region.random_point(&mut rng)).collect();
+            rdf.sample(&x, 0.0);
+        }
+        rdf.result().1
+    }
+
+    #[test]
+    fn ideal_gas_rdf() {
+        let g = uniform_rdf(Region::Periodic(periodic(6.0)), 500, 2.0);
+        assert!(g.iter().all(|g| (g - 1.0).abs() < 0.15), "{:?}", g);
+        // the shells sticking out of the box count less
+        let region = Region::Box(V::new(0.0, 0.0, 0.0), V::new(4.0, 3.0, 4.0))
+            .shrink(0.25)
+            .unwrap();
+        let g = uniform_rdf(region, 400, 2.0);
+        assert!(g.iter().all(|g| (g - 1.0).abs() < 0.15), "{:?}", g);
+        let sphere = Region::Container(Container::Sphere {
+            center: V::default(),
+            radius: 2.0,
+        });
+        let g = uniform_rdf(sphere, 300, 2.0);
+        assert!(g.iter().all(|g| (g - 1.0).abs() < 0.15), "{:?}", g);
+    }
+
+    #[test]
+    fn shrink() {
+        let sphere = |radius| {
+            Region::Container(Container::Sphere {
+                center: V::default(),
+                radius,
+            })
+        };
+        assert!((sphere(2.0).shrink(0.5).unwrap().volume() - sphere(1.5).volume()).abs() < 1e-12);
+        assert!(sphere(0.5).shrink(0.5).is_none());
+        let cylinder = Region::Container(Container::Cylinder {
+            base: V::default(),
+            axis: V::new(0.0, 0.0, 1.0),
+            radius: 2.0,
+        });
+        assert!((cylinder.shrink(0.25).unwrap().volume() - PI * 1.75 * 1.75 * 0.5).abs() < 1e-12);
+        assert!(cylinder.shrink(0.5).is_none());
+        let cube = Region::Box(V::new(0.0, 0.0, 0.0), V::new(1.0, 2.0, 3.0));
+        assert_eq!(cube.shrink(0.25).unwrap().volume(), 0.5 * 1.5 * 2.5);
+        assert!(cube.shrink(0.5).is_none());
+        assert!(Region::Periodic(periodic(1.0)).shrink(2.0).is_some());
+    }
+
+    #[test]
+    fn structure_factors() {
+        let l = V::new(5.0, 5.0, 5.0);
+        // an ideal gas has no structure
+        let mut s = StructureFactor::new(l, 3);
+        let mut rng = Rng::new(2);
+        let region = Region::Periodic(periodic(5.0));
+        for _ in 0..20 {
+            let x: Vec<V> = (0..300).map(|_| region.random_point(&mut rng)).collect();
+            s.sample(&x);
+        }
+        let (k, sk) = s.result();
+        // the first shell has |n| = 1, sqrt(2) and sqrt(3)
+        assert!(k[0] > s.dk && k[0] < 2.0 * s.dk);
+        assert!(sk.iter().all(|s| (s - 1.0).abs() < 0.3), "{:?}", sk);
+        let mean = sk.iter().sum::<f64>() / sk.len() as f64;
+        assert!((mean - 1.0).abs() < 0.05);
+
+        // a lattice of spacing 1 has none below 2 pi
+        let mut s = StructureFactor::new(l, 3);
+        let mut x = Vec::new();
+        for a in 0..5 {
+            for b in 0..5 {
+                for c in 0..5 {
+                    x.push(V::new(a as f64, b as f64, c as f64));
+                }
+            }
+        }
+        s.sample(&x);
+        assert!(s.result().1.iter().all(|s| s.abs() < 1e-9));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::dynamics::Ball;
+use crate::rng::Rng;
+use crate::vec3::{dot, V};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Thermostat {
+    // each ball gets a new velocity from the Maxwell-Boltzmann distribution at the given rate
+    Andersen { temperature: f64, rate: f64 },
+    // the velocities are rescaled to the temperature every period
+    Rescale { temperature: f64, period: f64 },
+    // the temperature relaxes with the time constant `tau`, the velocities are rescaled every period
+    Berendsen { temperature: f64, period: f64, tau: f64 },
+}
+
+impl Thermostat {
+    /// Whether the rates and times are positive and finite and the temperature non-negative and finite
+    pub fn is_valid(&self) -> bool {
+        let positive = |x: f64| x.is_finite() && x > 0.0;
+        let (temperature, times) = match *self {
+            Thermostat::Andersen { temperature, rate } => (temperature, positive(rate)),
+            Thermostat::Rescale {
+                temperature,
+                period,
+            } => (temperature, positive(period)),
+            Thermostat::Berendsen {
+                temperature,
+                period,
+                tau,
+            } => (temperature, positive(period) && positive(tau)),
+        };
+        times && temperature.is_finite() && temperature >= 0.0
+    }
+
+    /// Time before the next action of the thermostat on a system of `n` balls,
+    /// `None` if it is not valid
+    pub fn interval(&self, n: usize, rng: &mut Rng) -> Option<f64> {
+        if !self.is_valid() {
+            return None;
+        }
+        Some(match *self {
+            Thermostat::Andersen { rate, .. } => -rng.uniform().ln() / (rate * n as f64),
+            Thermostat::Rescale { period, .. } | Thermostat::Berendsen { period, .. } => period,
+        })
+    }
+
+    /// Factor by which the velocities are multiplied when the temperature is `t`,
+    /// the balls at rest are left alone
+    pub fn scale(&self, t: f64) -> f64 {
+        if t <= 0.0 {
+            return 1.0;
+        }
+        match *self {
+            Thermostat::Andersen { .. } => 1.0,
+            Thermostat::Rescale { temperature, .. } => (temperature / t).sqrt(),
+            Thermostat::Berendsen {
+                temperature,
+                period,
+                tau,
+            } => (1.0 + period / tau * (temperature / t - 1.0)).max(0.0).sqrt(),
+        }
+    }
+}
+
+/// Translational temperature of the balls, in the frame of the flow
+/// imposed at the `shear` rate by the sheared boundaries
+pub fn temperature(balls: &[Ball], shear: f64) -> f64 {
+    let e: f64 = balls
+        .iter()
+        .map(|a| {
+            let v = a.v - V::new(shear * a.x.1, 0.0, 0.0);
+            a.m * dot(v, v)
+        })
+        .sum();
+    e / (3.0 * balls.len() as f64)
+}
+
+/// Velocity sampled from the Maxwell-Boltzmann distribution
+pub fn maxwell(m: f64, t: f64, rng: &mut Rng) -> V {
+    let s = (t / m).sqrt();
+    s * V::new(rng.normal(), rng.normal(), rng.normal())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters() {
+        let mut rng = Rng::new(0);
+        let rescale = |period| Thermostat::Rescale {
+            temperature: 1.0,
+            period,
+        };
+        assert_eq!(rescale(0.5).interval(10, &mut rng), Some(0.5));
+        for &period in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(rescale(period).interval(10, &mut rng), None);
+        }
+        let andersen = |temperature, rate| Thermostat::Andersen { temperature, rate };
+        assert!(andersen(0.0, 1.0).interval(10, &mut rng).unwrap() >= 0.0);
+        assert!(!andersen(-1.0, 1.0).is_valid());
+        assert!(!andersen(1.0, -1.0).is_valid());
+        let berendsen = |tau| Thermostat::Berendsen {
+            temperature: 1.0,
+            period: 1.0,
+            tau,
+        };
+        assert!(berendsen(2.0).is_valid());
+        assert!(!berendsen(0.0).is_valid());
+    }
+
+    #[test]
+    fn scale() {
+        let rescale = Thermostat::Rescale {
+            temperature: 2.0,
+            period: 1.0,
+        };
+        assert!((rescale.scale(0.5) - 2.0).abs() < 1e-12);
+        // the balls at rest stay at rest
+        assert_eq!(rescale.scale(0.0), 1.0);
+        let berendsen = Thermostat::Berendsen {
+            temperature: 1.0,
+            period: 1.0,
+            tau: 4.0,
+        };
+        assert!((berendsen.scale(3.0) - (5.0f64 / 6.0).sqrt()).abs() < 1e-12);
+        // relaxing faster than the period does not go beyond zero
+        let fast = Thermostat::Berendsen {
+            temperature: 0.1,
+            period: 1.0,
+            tau: 0.1,
+        };
+        assert_eq!(fast.scale(1.0), 0.0);
+    }
+
+    #[test]
+    fn temperature_of_the_balls() {
+        let mut rng = Rng::new(1);
+        let balls: Vec<Ball> = (0..20_000)
+            .map(|i| {
+                let m = 1.0 + (i % 3) as f64;
+                Ball {
+                    x: V::new(0.0, i as f64 * 1e-3, 0.0),
+                    v: maxwell(m, 1.5, &mut rng) + V::new(0.2 * i as f64 * 1e-3, 0.0, 0.0),
+                    a: V::default(),
+                    w: V::default(),
+                    m,
+                    r: 0.1,
+                    i: 0.0,
+                    e: None,
+                    s: 0,
+                }
+            })
+            .collect();
+        // the flow of a shear rate of 0.2 is left out
+        assert!((temperature(&balls, 0.2) - 1.5).abs() < 0.03);
+    }
+}
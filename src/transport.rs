@@ -0,0 +1,165 @@
+use crate::vec3::{dot, V};
+use std::collections::VecDeque;
+
+/// Mean squared displacement and velocity autocorrelation function of the balls, with every
+/// sample as a time origin, up to `max_lag` samples apart. The samples have to be evenly spaced.
+/// The earlier samples are dropped as time origins when the number of balls changes.
+#[derive(Debug, Clone)]
+pub struct Correlations {
+    pub max_lag: usize,
+    // unwrapped positions and velocities of the last samples, the newest first
+    positions: VecDeque<Vec<V>>,
+    velocities: VecDeque<Vec<V>>,
+    msd: Vec<f64>,
+    vacf: Vec<f64>,
+    counts: Vec<f64>,
+    first: f64,
+    last: f64,
+    samples: usize,
+}
+
+impl Correlations {
+    pub fn new(max_lag: usize) -> Correlations {
+        Correlations {
+            max_lag,
+            positions: VecDeque::new(),
+            velocities: VecDeque::new(),
+            msd: vec![0.0; max_lag + 1],
+            vacf: vec![0.0; max_lag + 1],
+            counts: vec![0.0; max_lag + 1],
+            first: 0.0,
+            last: 0.0,
+            samples: 0,
+        }
+    }
+
+    /// Add the unwrapped positions `x` and the velocities `v` at time `t`
+    pub fn sample(&mut self, x: Vec<V>, v: Vec<V>, t: f64) {
+        if self.samples == 0 {
+            self.first = t;
+        }
+        self.last = t;
+        self.samples += 1;
+        if self.positions.front().is_some_and(|x0| x0.len() != x.len()) {
+            self.positions.clear();
+            self.velocities.clear();
+        }
+        self.positions.push_front(x);
+        self.velocities.push_front(v);
+        self.positions.truncate(self.max_lag + 1);
+        self.velocities.truncate(self.max_lag + 1);
+        let (x, v) = (&self.positions[0], &self.velocities[0]);
+        for lag in 0..self.positions.len() {
+            let (x0, v0) = (&self.positions[lag], &self.velocities[lag]);
+            for i in 0..x.len() {
+                let d = x[i] - x0[i];
+                self.msd[lag] += dot(d, d);
+                self.vacf[lag] += dot(v[i], v0[i]);
+            }
+            self.counts[lag] += x.len() as f64;
+        }
+    }
+
+    /// Time between the samples
+    pub fn interval(&self) -> f64 {
+        if self.samples > 1 {
+            (self.last - self.first) / (self.samples - 1) as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Lag times, mean squared displacement and velocity autocorrelation function,
+    /// for the lags seen so far
+    pub fn result(&self) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        let dt = self.interval();
+        let lags = self.positions.len();
+        (
+            (0..lags).map(|l| l as f64 * dt).collect(),
+            (0..lags).map(|l| self.msd[l] / self.counts[l]).collect(),
+            (0..lags).map(|l| self.vacf[l] / self.counts[l]).collect(),
+        )
+    }
+
+    /// Self-diffusion coefficient from the slope of the mean squared displacement over the
+    /// second half of the lags (Einstein) and from the integral of the velocity autocorrelation
+    /// function (Green-Kubo)
+    pub fn diffusion(&self) -> (f64, f64) {
+        let (t, msd, vacf) = self.result();
+        let n = t.len();
+        if n < 3 {
+            return (f64::NAN, f64::NAN);
+        }
+        // least squares over the second half
+        let h: Vec<usize> = (n / 2..n).collect();
+        let m = h.len() as f64;
+        let tm = h.iter().map(|&l| t[l]).sum::<f64>() / m;
+        let ym = h.iter().map(|&l| msd[l]).sum::<f64>() / m;
+        let sxy: f64 = h.iter().map(|&l| (t[l] - tm) * (msd[l] - ym)).sum();
+        let sxx: f64 = h.iter().map(|&l| (t[l] - tm) * (t[l] - tm)).sum();
+        let einstein = sxy / sxx / 6.0;
+        // trapezoids
+        let integral: f64 = (1..n).map(|l| 0.5 * (vacf[l - 1] + vacf[l]) * (t[l] - t[l - 1])).sum();
+        (einstein, integral / 3.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn ballistic() {
+        let u = [V::new(1.0, 0.0, 0.0), V::new(0.0, 2.0, -2.0)];
+        let mut c = Correlations::new(4);
+        for s in 0..10 {
+            let t = 0.5 * s as f64;
+            c.sample(u.iter().map(|&u| t * u).collect(), u.to_vec(), t);
+        }
+        let (t, msd, vacf) = c.result();
+        assert_eq!(t, vec![0.0, 0.5, 1.0, 1.5, 2.0]);
+        // mean of |u|^2 is 4.5, the displacements grow as u t
+        for l in 0..5 {
+            assert!((msd[l] - 4.5 * t[l] * t[l]).abs() < 1e-12);
+            assert!((vacf[l] - 4.5).abs() < 1e-12);
+        }
+        let (_, green_kubo) = c.diffusion();
+        assert!((green_kubo - 4.5 * 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn random_walk() {
+        // steps of variance 0.02 along each axis every 0.1, so that D = 0.1
+        let n = 1000;
+        let mut rng = Rng::new(3);
+        let mut x = vec![V::default(); n];
+        let mut c = Correlations::new(20);
+        for s in 0..200 {
+            for x in x.iter_mut() {
+                *x += 0.02f64.sqrt() * V::new(rng.normal(), rng.normal(), rng.normal());
+            }
+            c.sample(x.clone(), vec![V::default(); n], 0.1 * s as f64);
+        }
+        let (einstein, green_kubo) = c.diffusion();
+        assert!((einstein - 0.1).abs() < 0.005, "{}", einstein);
+        assert_eq!(green_kubo, 0.0);
+        assert!(Correlations::new(1).diffusion().0.is_nan());
+    }
+
+    #[test]
+    fn new_balls() {
+        let mut c = Correlations::new(3);
+        let v = |n: usize| vec![V::new(1.0, 0.0, 0.0); n];
+        c.sample(v(2), v(2), 0.0);
+        c.sample(v(2), v(2), 1.0);
+        // the samples with two balls are no time origins for those with three
+        c.sample(v(3), v(3), 2.0);
+        let (t, msd, vacf) = c.result();
+        assert_eq!(t, vec![0.0]);
+        assert_eq!((msd[0], vacf[0]), (0.0, 1.0));
+        c.sample(v(3), v(3), 3.0);
+        assert_eq!(c.result().0, vec![0.0, 1.0]);
+        assert_eq!(c.interval(), 1.0);
+    }
+}
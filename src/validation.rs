@@ -0,0 +1,388 @@
+use crate::dynamics::{Ball, Container, Shape, Wall};
+use crate::vec3::{cross, dot, V};
+use std::fmt;
+
+/// What makes a state unfit for the simulation, with the indices of the objects involved
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Problem {
+    BallNotFinite(usize),
+    BallRadius(usize),
+    BallMass(usize),
+    BallRestitution(usize),
+    WallNotFinite(usize),
+    WallDegenerate(usize),
+    WallPolygon(usize),
+    WallMass(usize),
+    WallRestitution(usize),
+    WallTemperature(usize),
+    ContainerDegenerate(usize),
+    BondBalls(usize, usize),
+    BondLengths(usize, usize),
+    // found once the objects themselves are sound
+    BallsOverlap(usize, usize),
+    BallWallOverlap(usize, usize),
+    BallOutsideContainer(usize, usize),
+    BondStretched(usize, usize),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::BallNotFinite(i) => write!(f, "ball {}: infinite or NaN value", i),
+            Problem::BallRadius(i) => write!(f, "ball {}: the radius has to be positive", i),
+            Problem::BallMass(i) => write!(
+                f,
+                "ball {}: the mass has to be positive and the moment of inertia non-negative",
+                i
+            ),
+            Problem::BallRestitution(i) => {
+                write!(f, "ball {}: the coefficient of restitution has to be in [0, 1]", i)
+            }
+            Problem::WallNotFinite(j) => write!(f, "wall {}: infinite or NaN value", j),
+            Problem::WallDegenerate(j) => write!(f, "wall {}: j and k do not span a plane", j),
+            Problem::WallPolygon(j) => write!(
+                f,
+                "wall {}: the polygon needs at least 3 corners, in a plane and in convex order",
+                j
+            ),
+            Problem::WallMass(j) => write!(f, "wall {}: the mass has to be positive", j),
+            Problem::WallRestitution(j) => {
+                write!(f, "wall {}: the coefficient of restitution has to be in [0, 1]", j)
+            }
+            Problem::WallTemperature(j) => {
+                write!(f, "wall {}: the temperature has to be non-negative and finite", j)
+            }
+            Problem::ContainerDegenerate(c) => {
+                write!(f, "container {}: the radius and the axis have to be positive and finite", c)
+            }
+            Problem::BondBalls(i, k) => write!(f, "bond {}-{}: no such balls", i, k),
+            Problem::BondLengths(i, k) => write!(
+                f,
+                "bond {}-{}: the lengths have to satisfy 0 <= min <= max",
+                i, k
+            ),
+            Problem::BallsOverlap(i, k) => write!(f, "balls {} and {} overlap", i, k),
+            Problem::BallWallOverlap(i, j) => write!(f, "ball {} intersects wall {}", i, j),
+            Problem::BallOutsideContainer(i, c) => {
+                write!(f, "ball {} is not inside container {}", i, c)
+            }
+            Problem::BondStretched(i, k) => {
+                write!(f, "bond {}-{}: the length is out of bounds", i, k)
+            }
+        }
+    }
+}
+
+fn finite(x: V) -> bool {
+    x.0.is_finite() && x.1.is_finite() && x.2.is_finite()
+}
+
+/// Problems of the ball `i` on its own
+pub fn ball_problems(i: usize, a: &Ball) -> Vec<Problem> {
+    let mut out = Vec::new();
+    if !(finite(a.x) && finite(a.v) && finite(a.a) && finite(a.w))
+        || !(a.m.is_finite() && a.r.is_finite() && a.i.is_finite())
+    {
+        out.push(Problem::BallNotFinite(i));
+    }
+    if a.r.is_nan() || a.r <= 0.0 {
+        out.push(Problem::BallRadius(i));
+    }
+    if a.m.is_nan() || a.m <= 0.0 || a.i.is_nan() || a.i < 0.0 {
+        out.push(Problem::BallMass(i));
+    }
+    if a.e.is_some_and(|e| !(0.0..=1.0).contains(&e)) {
+        out.push(Problem::BallRestitution(i));
+    }
+    out
+}
+
+/// Problems of the wall `j` on its own
+pub fn wall_problems(j: usize, w: &Wall) -> Vec<Problem> {
+    let mut out = Vec::new();
+    let corners = match &w.shape {
+        Shape::Polygon(c) => c.iter().all(|c| c.0.is_finite() && c.1.is_finite()),
+        _ => true,
+    };
+    if !(finite(w.x) && finite(w.v) && finite(w.j) && finite(w.k) && corners) {
+        out.push(Problem::WallNotFinite(j));
+    } else if cross(w.j, w.k).norm() == 0.0 || w.outline().len() < 3 {
+        out.push(Problem::WallDegenerate(j));
+    }
+    // the mass can be infinite
+    if w.m.is_nan() || w.m <= 0.0 {
+        out.push(Problem::WallMass(j));
+    }
+    if w.e.is_some_and(|e| !(0.0..=1.0).contains(&e)) {
+        out.push(Problem::WallRestitution(j));
+    }
+    if w.temperature.is_some_and(|t| !(t.is_finite() && t >= 0.0)) {
+        out.push(Problem::WallTemperature(j));
+    }
+    out
+}
+
+/// Problems of the corners of a polygonal wall `j`, in order
+pub fn polygon_problems(j: usize, corners: &[V]) -> Vec<Problem> {
+    if !corners.iter().all(|&c| finite(c)) {
+        return vec![Problem::WallNotFinite(j)];
+    }
+    if corners.len() < 3 {
+        return vec![Problem::WallPolygon(j)];
+    }
+    let n = corners.len();
+    let x = corners[0];
+    let normal = cross(corners[1] - x, corners[n - 1] - x);
+    if normal.norm() == 0.0 {
+        return vec![Problem::WallDegenerate(j)];
+    }
+    let normal = normal / normal.norm();
+    let size = corners.iter().fold(0.0, |s: f64, &c| s.max((c - x).norm()));
+    let flat = corners.iter().all(|&c| dot(c - x, normal).abs() <= 1e-9 * size);
+    // turning the same way at each corner, once around
+    let mut turn = 0.0;
+    let mut convex = true;
+    for i in 0..n {
+        let a = corners[(i + 1) % n] - corners[i];
+        let b = corners[(i + 2) % n] - corners[(i + 1) % n];
+        let z = dot(cross(a, b), normal);
+        convex &= z >= -1e-9 * a.norm() * b.norm();
+        turn += z.atan2(dot(a, b));
+    }
+    convex &= (turn - 2.0 * std::f64::consts::PI).abs() < 1e-6;
+    if flat && convex {
+        Vec::new()
+    } else {
+        vec![Problem::WallPolygon(j)]
+    }
+}
+
+/// Problems of the container `c` on its own
+pub fn container_problems(c: usize, container: &Container) -> Vec<Problem> {
+    let sound = match *container {
+        Container::Sphere { center, radius } => finite(center) && radius.is_finite() && radius > 0.0,
+        Container::Cylinder { base, axis, radius } => {
+            finite(base) && finite(axis) && axis.norm() > 0.0 && radius.is_finite() && radius > 0.0
+        }
+    };
+    if sound {
+        Vec::new()
+    } else {
+        vec![Problem::ContainerDegenerate(c)]
+    }
+}
+
+/// Problems of a bond between the balls `i` and `k`, out of `n` balls
+pub fn bond_problems(i: usize, k: usize, min: f64, max: f64, n: usize) -> Vec<Problem> {
+    if i >= n || k >= n || i == k {
+        vec![Problem::BondBalls(i, k)]
+    } else if !(0.0 <= min && min <= max) {
+        vec![Problem::BondLengths(i, k)]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball() -> Ball {
+        Ball {
+            x: V::default(),
+            v: V::new(1.0, 0.0, 0.0),
+            a: V::default(),
+            w: V::default(),
+            m: 1.0,
+            r: 0.5,
+            i: 0.1,
+            e: None,
+            s: 0,
+        }
+    }
+
+    fn wall() -> Wall {
+        Wall {
+            x: V::default(),
+            v: V::default(),
+            j: V::new(1.0, 0.0, 0.0),
+            k: V::new(0.0, 1.0, 0.0),
+            m: f64::INFINITY,
+            e: None,
+            temperature: None,
+            motion: None,
+            shape: Shape::Parallelogram,
+        }
+    }
+
+    #[test]
+    fn balls() {
+        assert_eq!(ball_problems(0, &ball()), Vec::new());
+        let nan = Ball {
+            v: V::new(f64::NAN, 0.0, 0.0),
+            ..ball()
+        };
+        assert_eq!(ball_problems(1, &nan), vec![Problem::BallNotFinite(1)]);
+        let point = Ball { r: 0.0, ..ball() };
+        assert_eq!(ball_problems(2, &point), vec![Problem::BallRadius(2)]);
+        let radius = Ball {
+            r: f64::NAN,
+            ..ball()
+        };
+        assert_eq!(
+            ball_problems(3, &radius),
+            vec![Problem::BallNotFinite(3), Problem::BallRadius(3)]
+        );
+        let inertia = Ball { i: -1.0, ..ball() };
+        assert_eq!(ball_problems(4, &inertia), vec![Problem::BallMass(4)]);
+        let massless = Ball { m: 0.0, ..ball() };
+        assert_eq!(ball_problems(5, &massless), vec![Problem::BallMass(5)]);
+        let bouncy = Ball {
+            e: Some(1.5),
+            ..ball()
+        };
+        assert_eq!(ball_problems(6, &bouncy), vec![Problem::BallRestitution(6)]);
+        let sticky = Ball {
+            e: Some(0.0),
+            ..ball()
+        };
+        assert_eq!(ball_problems(7, &sticky), Vec::new());
+    }
+
+    #[test]
+    fn walls() {
+        assert_eq!(wall_problems(0, &wall()), Vec::new());
+        let nan = Wall {
+            x: V::new(0.0, f64::INFINITY, 0.0),
+            ..wall()
+        };
+        assert_eq!(wall_problems(1, &nan), vec![Problem::WallNotFinite(1)]);
+        let flat = Wall {
+            k: V::new(2.0, 0.0, 0.0),
+            ..wall()
+        };
+        assert_eq!(wall_problems(2, &flat), vec![Problem::WallDegenerate(2)]);
+        let line = Wall {
+            shape: Shape::Polygon(vec![(0.0, 0.0), (1.0, 0.0)]),
+            ..wall()
+        };
+        assert_eq!(wall_problems(3, &line), vec![Problem::WallDegenerate(3)]);
+        let corner = Wall {
+            shape: Shape::Polygon(vec![(0.0, 0.0), (1.0, 0.0), (0.0, f64::NAN)]),
+            ..wall()
+        };
+        assert_eq!(wall_problems(4, &corner), vec![Problem::WallNotFinite(4)]);
+        let massless = Wall { m: 0.0, ..wall() };
+        assert_eq!(wall_problems(5, &massless), vec![Problem::WallMass(5)]);
+        let bouncy = Wall {
+            e: Some(-0.5),
+            ..wall()
+        };
+        assert_eq!(wall_problems(6, &bouncy), vec![Problem::WallRestitution(6)]);
+        for &t in &[-1.0, f64::NAN, f64::INFINITY] {
+            let hot = Wall {
+                temperature: Some(t),
+                ..wall()
+            };
+            assert_eq!(wall_problems(7, &hot), vec![Problem::WallTemperature(7)]);
+        }
+        let cold = Wall {
+            temperature: Some(0.0),
+            ..wall()
+        };
+        assert_eq!(wall_problems(8, &cold), Vec::new());
+    }
+
+    #[test]
+    fn containers_and_bonds() {
+        let sphere = |radius| Container::Sphere {
+            center: V::default(),
+            radius,
+        };
+        assert_eq!(container_problems(0, &sphere(1.0)), Vec::new());
+        assert_eq!(
+            container_problems(1, &sphere(0.0)),
+            vec![Problem::ContainerDegenerate(1)]
+        );
+        let cylinder = Container::Cylinder {
+            base: V::default(),
+            axis: V::default(),
+            radius: 1.0,
+        };
+        assert_eq!(
+            container_problems(2, &cylinder),
+            vec![Problem::ContainerDegenerate(2)]
+        );
+
+        assert_eq!(bond_problems(0, 1, 1.0, 2.0, 2), Vec::new());
+        assert_eq!(
+            bond_problems(0, 0, 1.0, 2.0, 2),
+            vec![Problem::BondBalls(0, 0)]
+        );
+        assert_eq!(
+            bond_problems(0, 2, 1.0, 2.0, 2),
+            vec![Problem::BondBalls(0, 2)]
+        );
+        assert_eq!(
+            bond_problems(0, 1, 2.0, 1.0, 2),
+            vec![Problem::BondLengths(0, 1)]
+        );
+        assert_eq!(
+            bond_problems(0, 1, -1.0, 1.0, 2),
+            vec![Problem::BondLengths(0, 1)]
+        );
+        assert_eq!(
+            bond_problems(0, 1, f64::NAN, 1.0, 2),
+            vec![Problem::BondLengths(0, 1)]
+        );
+    }
+
+    #[test]
+    fn polygons() {
+        let square = |z: f64| {
+            vec![
+                V::new(0.0, 0.0, 0.0),
+                V::new(1.0, 0.0, 0.0),
+                V::new(1.0, 1.0, z),
+                V::new(0.0, 1.0, 0.0),
+            ]
+        };
+        assert_eq!(polygon_problems(0, &square(0.0)), Vec::new());
+        assert_eq!(
+            polygon_problems(0, &square(0.1)),
+            vec![Problem::WallPolygon(0)]
+        );
+        let arrow = [
+            V::new(0.0, 0.0, 0.0),
+            V::new(2.0, 0.0, 0.0),
+            V::new(2.0, 2.0, 0.0),
+            V::new(1.0, 0.5, 0.0),
+            V::new(0.0, 2.0, 0.0),
+        ];
+        assert_eq!(polygon_problems(1, &arrow), vec![Problem::WallPolygon(1)]);
+        // a pentagram turns the same way at each corner, but twice around
+        let star: Vec<V> = (0..5)
+            .map(|i| {
+                let a = 4.0 * std::f64::consts::PI * i as f64 / 5.0;
+                V::new(a.cos(), a.sin(), 0.0)
+            })
+            .collect();
+        assert_eq!(polygon_problems(2, &star), vec![Problem::WallPolygon(2)]);
+        assert_eq!(
+            polygon_problems(3, &square(0.0)[..2]),
+            vec![Problem::WallPolygon(3)]
+        );
+        let line = [
+            V::new(0.0, 0.0, 0.0),
+            V::new(1.0, 0.0, 0.0),
+            V::new(2.0, 0.0, 0.0),
+        ];
+        assert_eq!(polygon_problems(4, &line), vec![Problem::WallDegenerate(4)]);
+        let nan = [
+            V::new(0.0, 0.0, 0.0),
+            V::new(1.0, 0.0, 0.0),
+            V::new(f64::NAN, 0.0, 0.0),
+        ];
+        assert_eq!(polygon_problems(5, &nan), vec![Problem::WallNotFinite(5)]);
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Default)]
 pub struct V(pub f64, pub f64, pub f64);
 
 impl V {
@@ -99,3 +99,26 @@ impl std::fmt::Debug for V {
         write!(f, "{}", self)
     }
 }
+
+impl std::ops::Index<usize> for V {
+    type Output = f64;
+    fn index(&self, i: usize) -> &f64 {
+        match i {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("index out of range"),
+        }
+    }
+}
+
+impl std::ops::IndexMut<usize> for V {
+    fn index_mut(&mut self, i: usize) -> &mut f64 {
+        match i {
+            0 => &mut self.0,
+            1 => &mut self.1,
+            2 => &mut self.2,
+            _ => panic!("index out of range"),
+        }
+    }
+}